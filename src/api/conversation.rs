@@ -0,0 +1,286 @@
+use std::sync::Arc;
+
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::HeaderMap,
+    response::{IntoResponse, Response},
+};
+use log::{error, info};
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+
+use crate::{
+    api::{
+        checker::{
+            FormatQuery, Outcome, body_option, complete, output_diff, output_format, parse_output,
+            request_config, revised_text,
+        },
+        history,
+        utils::{error_response, response_handler, unauthorized},
+    },
+    common::{
+        self,
+        database::Database,
+        http::Http,
+        provider::{AiRequest, Role, Turn},
+        tokens,
+    },
+    models::{
+        claim::Claims, conversation::Conversation, meeting::MeetingMinutes, template::TemplateRef,
+    },
+};
+
+// 作成者以外には存在しないものとして扱う
+async fn find_own(db: &Database, claims: &Claims, id: &str) -> Result<Conversation, Response> {
+    match common::conversation::get(db, id).await {
+        Ok(Some(conversation)) if conversation.user_id == claims.user_id => Ok(conversation),
+        Ok(_) => Err(error_response(
+            StatusCode::NOT_FOUND,
+            format!("conversation not found: {}", id),
+        )),
+        Err(e) => Err(error_response(StatusCode::INTERNAL_SERVER_ERROR, e)),
+    }
+}
+
+/// 最初のリクエストと結果から会話を作成し、会話のIDを返す
+/// 保存に失敗しても結果は返せるよう、エラーはログに残して None とする
+pub async fn start(
+    db: &Database,
+    user_id: &str,
+    target_ai: &str,
+    template: &TemplateRef,
+    body: &Value,
+    request: &AiRequest,
+    outcome: &Outcome,
+) -> Option<String> {
+    let now = chrono::Utc::now().to_rfc3339();
+    let conversation = Conversation {
+        id: uuid::Uuid::new_v4().to_string(),
+        user_id: user_id.to_string(),
+        target_ai: target_ai.to_string(),
+        prompt_type: template.name.clone(),
+        template_version: template.version,
+        system: request.system.clone(),
+        structured: request.response_schema.is_some(),
+        chat: match template.name.as_str() {
+            "chat" => Some(body_option(body, "chat").ok().flatten().unwrap_or_default()),
+            _ => None,
+        },
+        turns: vec![Turn::user(&request.content), Turn::model(&outcome.output)],
+        created_at: now.clone(),
+        updated_at: now,
+    };
+    match common::conversation::create(db, &conversation).await {
+        Ok(_) => Some(conversation.id),
+        Err(e) => {
+            error!("Failed to save conversation: {}", e);
+            None
+        }
+    }
+}
+
+/// # get
+///
+/// 会話のすべてのやり取りを返す
+///
+/// ## HTTP情報
+///
+/// - **メソッド**: GET
+/// - **パス**: /api/private/conversations/{id}
+/// - **認証**: 必要
+///
+/// ## レスポンス
+///
+/// ```json
+/// {
+///   "message": "success",
+///   "data": {
+///     "id": "8a21...",
+///     "target_ai": "gemini",
+///     "prompt_type": "mail",
+///     "template_version": 3,
+///     "turns": [{"role": "user", "text": "..."}, {"role": "model", "text": "(markdown)"}],
+///     "created_at": "...",
+///     "updated_at": "..."
+///   }
+/// }
+/// ```
+///
+/// ### エラー時
+/// - **404**: 会話が存在しない
+pub async fn get(
+    claims: Claims,
+    Path(id): Path<String>,
+    State(db): State<Arc<Database>>,
+) -> Response {
+    if !claims.is_ok() {
+        return unauthorized();
+    }
+
+    match find_own(&db, &claims, &id).await {
+        Ok(conversation) => response_handler(
+            StatusCode::OK,
+            "success".to_string(),
+            Some(conversation.to_thread()),
+            None,
+        )
+        .into_response(),
+        Err(res) => res,
+    }
+}
+
+/// # reply
+///
+/// 前回の結果に対する続きの依頼 (「もっと短く」「もう少しくだけた表現で」など) を送る
+/// これまでのやり取りを複数ターンの会話としてAIに渡す
+/// 上限 (`CONVERSATION_MAX_TOKENS`) を超える場合は古いやり取りから省く
+///
+/// ## HTTP情報
+///
+/// - **メソッド**: POST
+/// - **パス**: /api/private/conversations/{id}
+/// - **認証**: 必要
+///
+/// ## ペイロード
+///
+/// ```json
+/// {
+///   "message": "もっと短くしてください",
+///   "generation_config": {"temperature": 0.7}
+/// }
+/// ```
+///
+/// ## レスポンス
+///
+/// `switcher` の成功時の data に、会話全体 (`conversation`) と省いたターンの数 (`omitted_turns`) を加えたもの
+/// `diff` は前回の結果の全文との差分
+/// 出力形式 (`format` クエリパラメータ・`Accept` ヘッダー) は `switcher` と同じ
+///
+/// ### エラー時
+/// - **400**: 入力の誤り
+/// - **406**: 未対応の出力形式
+/// - **404**: 会話が存在しない
+/// - **422 / 502 / 504**: `switcher` と同じ
+pub async fn reply(
+    claims: Claims,
+    Path(id): Path<String>,
+    State(db): State<Arc<Database>>,
+    State(http): State<Http>,
+    Query(format_query): Query<FormatQuery>,
+    headers: HeaderMap,
+    Json(body): Json<Value>,
+) -> Response {
+    if !claims.is_ok() {
+        return unauthorized();
+    }
+    let format = match output_format(&format_query, &headers) {
+        Ok(format) => format,
+        Err(e) => return error_response(StatusCode::NOT_ACCEPTABLE, e),
+    };
+
+    let message = body["message"].as_str().unwrap_or_default();
+    if message.trim().is_empty() {
+        return error_response(StatusCode::BAD_REQUEST, "message is empty".to_string());
+    }
+    let mut conversation = match find_own(&db, &claims, &id).await {
+        Ok(v) => v,
+        Err(res) => return res,
+    };
+    let config = match request_config(&conversation.prompt_type, &body) {
+        Ok(v) => v,
+        Err(err) => return error_response(err.status(), err.to_string()),
+    };
+
+    let (turns, omitted) = conversation.context(
+        message,
+        tokens::conversation_budget(&conversation.target_ai),
+    );
+    if omitted > 0 {
+        info!("conversation {}: {} turns omitted", id, omitted);
+    }
+    let mut request = AiRequest::new(message.to_string(), config).with_history(turns);
+    if let Some(system) = &conversation.system {
+        request = request.with_system(system.clone());
+    }
+    if conversation.structured {
+        request = request.with_response_schema(MeetingMinutes::schema());
+    }
+
+    let reference = TemplateRef {
+        name: conversation.prompt_type.clone(),
+        version: conversation.template_version,
+    };
+    // 候補は最初のリクエストの調整条件で取り出す
+    let options = json!({ "chat": conversation.chat });
+    let start = std::time::Instant::now();
+    let outcome = match complete(
+        &http,
+        &conversation.target_ai,
+        &conversation.prompt_type,
+        &options,
+        &request,
+        None,
+    )
+    .await
+    {
+        Ok(v) => v,
+        Err(err) => {
+            history::record_failure(
+                &db,
+                &claims.user_id,
+                &conversation.target_ai,
+                &reference,
+                message,
+                &err,
+                start.elapsed().as_secs(),
+            )
+            .await;
+            return response_handler(
+                err.status(),
+                err.code().to_string(),
+                None,
+                Some(err.to_string()),
+            )
+            .into_response();
+        }
+    };
+
+    // 差分は前回の結果の全文と比べる
+    let previous = conversation
+        .turns
+        .iter()
+        .rev()
+        .find(|turn| turn.role == Role::Model)
+        .and_then(|turn| parse_output(&conversation.prompt_type, &turn.text, &options));
+    let diff = revised_text(&conversation.prompt_type, previous.as_ref()).and_then(|original| {
+        revised_text(&conversation.prompt_type, outcome.parsed.as_ref())
+            .map(|revised| output_diff(original, revised))
+    });
+
+    // やり取りはすべて保存する (省いたターンも残す)
+    conversation.turns.push(Turn::user(message));
+    conversation.turns.push(Turn::model(&outcome.output));
+    if let Err(e) = common::conversation::save(&db, &mut conversation).await {
+        error!("Failed to save conversation {}: {}", id, e);
+    }
+
+    let mut data = outcome.to_json(&reference);
+    data["diff"] = json!(diff);
+    data["id"] = json!(
+        history::record(
+            &db,
+            &claims.user_id,
+            &conversation.target_ai,
+            &reference,
+            message,
+            &outcome,
+        )
+        .await
+    );
+    data["conversation_id"] = json!(conversation.id);
+    data["conversation"] = conversation.to_thread();
+    data["omitted_turns"] = json!(omitted);
+
+    outcome.respond(format, data)
+}
@@ -0,0 +1,330 @@
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    response::{IntoResponse, Response},
+};
+use log::{error, info};
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+
+use crate::{
+    api::{
+        checker::Outcome,
+        utils::{error_response, response_handler, unauthorized},
+    },
+    common::{self, database::Database, provider::AiError},
+    models::{
+        claim::Claims,
+        history::{History, HistoryError, HistoryQuery},
+        mail::{MailRefactoring, fill_placeholders},
+        template::TemplateRef,
+    },
+};
+
+// 作成者以外には存在しないものとして扱う
+async fn find_own(db: &Database, claims: &Claims, id: &str) -> Result<History, Response> {
+    match common::history::get(db, id).await {
+        Ok(Some(history)) if history.user_id == claims.user_id => Ok(history),
+        Ok(_) => Err(error_response(
+            StatusCode::NOT_FOUND,
+            format!("history not found: {}", id),
+        )),
+        Err(e) => Err(error_response(StatusCode::INTERNAL_SERVER_ERROR, e)),
+    }
+}
+
+/// 成功したリクエストを履歴に保存し、履歴のIDを返す
+/// 保存に失敗しても結果は返せるよう、エラーはログに残して None とする
+pub async fn record(
+    db: &Database,
+    user_id: &str,
+    target_ai: &str,
+    template: &TemplateRef,
+    message: &str,
+    outcome: &Outcome,
+) -> Option<String> {
+    let history = History {
+        id: uuid::Uuid::new_v4().to_string(),
+        user_id: user_id.to_string(),
+        provider: target_ai.to_string(),
+        model: outcome.response.model.clone(),
+        prompt_type: template.name.clone(),
+        template_version: template.version,
+        input: message.to_string(),
+        output: outcome.output.clone(),
+        elapsed: outcome.elapsed,
+        usage: outcome.response.usage.clone(),
+        error: None,
+        created_at: chrono::Utc::now().to_rfc3339(),
+    };
+    save(db, history).await
+}
+
+/// 失敗したリクエストをエラーの種類とともに履歴に保存し、履歴のIDを返す
+/// 出力はないため model と output は空にする
+pub async fn record_failure(
+    db: &Database,
+    user_id: &str,
+    target_ai: &str,
+    template: &TemplateRef,
+    message: &str,
+    err: &AiError,
+    elapsed: u64,
+) -> Option<String> {
+    let history = History {
+        id: uuid::Uuid::new_v4().to_string(),
+        user_id: user_id.to_string(),
+        provider: target_ai.to_string(),
+        model: String::new(),
+        prompt_type: template.name.clone(),
+        template_version: template.version,
+        input: message.to_string(),
+        output: String::new(),
+        elapsed,
+        usage: None,
+        error: Some(HistoryError {
+            code: err.code().to_string(),
+            message: err.to_string(),
+        }),
+        created_at: chrono::Utc::now().to_rfc3339(),
+    };
+    save(db, history).await
+}
+
+async fn save(db: &Database, history: History) -> Option<String> {
+    match common::history::create(db, &history).await {
+        Ok(_) => Some(history.id),
+        Err(e) => {
+            error!("Failed to save history: {}", e);
+            None
+        }
+    }
+}
+
+/// # list
+///
+/// ログインユーザーのAIへのリクエストの履歴を新しい順に返す
+/// 失敗したリクエストは `error` にエラーの種類を含む
+///
+/// ## HTTP情報
+///
+/// - **メソッド**: GET
+/// - **パス**: /api/private/history
+/// - **認証**: 必要
+///
+/// ## クエリパラメータ
+///
+/// - `prompt_type`: 任意。プロンプトタイプで絞り込む
+/// - `from`, `to`: 任意。作成日 (日本時間, `2025-04-01` 形式) の範囲。両端を含む
+/// - `q`: 任意。入力・出力に含まれる文字列 (大文字・小文字を区別しない)
+/// - `cursor`: 任意。前のページのレスポンスの `next_cursor`。省略した場合は最初のページ
+/// - `per_page`: 任意。既定は 20、最大 100
+///
+/// ## レスポンス
+///
+/// ```json
+/// {
+///   "message": "success",
+///   "data": {
+///     "per_page": 20,
+///     "next_cursor": "2025-04-01T01:00:00+00:00",
+///     "items": [{
+///       "id": "3f0c...", "provider": "gemini", "model": "gemini-2.5-pro", "prompt_type": "mail",
+///       "template_version": 3, "input": "...", "output": "(markdown)", "elapsed": 12,
+///       "usage": {"prompt_tokens": 1200, "output_tokens": 800, "total_tokens": 2000},
+///       "error": null,
+///       "created_at": "2025-04-01T01:00:00+00:00"
+///     }]
+///   }
+/// }
+/// ```
+///
+/// - `next_cursor`: 次のページがない場合は null
+/// - `error`: 失敗したリクエストのみ。`{"code": "timeout", "message": "エラーメッセージ"}`
+pub async fn list(
+    claims: Claims,
+    Query(query): Query<HistoryQuery>,
+    State(db): State<Arc<Database>>,
+) -> Response {
+    if !claims.is_ok() {
+        return unauthorized();
+    }
+
+    let (items, next_cursor) = match common::history::list(&db, &claims.user_id, &query).await {
+        Ok(v) => v,
+        Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+    };
+
+    response_handler(
+        StatusCode::OK,
+        "success".to_string(),
+        Some(json!({
+            "per_page": query.per_page(),
+            "next_cursor": next_cursor,
+            "items": items,
+        })),
+        None,
+    )
+    .into_response()
+}
+
+/// # get
+///
+/// 履歴を1件返す
+///
+/// ## HTTP情報
+///
+/// - **メソッド**: GET
+/// - **パス**: /api/private/history/{id}
+/// - **認証**: 必要
+///
+/// ### エラー時
+/// - **404**: 履歴が存在しない
+pub async fn get(
+    claims: Claims,
+    Path(id): Path<String>,
+    State(db): State<Arc<Database>>,
+) -> Response {
+    if !claims.is_ok() {
+        return unauthorized();
+    }
+
+    match find_own(&db, &claims, &id).await {
+        Ok(history) => response_handler(
+            StatusCode::OK,
+            "success".to_string(),
+            Some(json!(history)),
+            None,
+        )
+        .into_response(),
+        Err(res) => res,
+    }
+}
+
+/// # delete
+///
+/// 履歴を削除する
+///
+/// ## HTTP情報
+///
+/// - **メソッド**: DELETE
+/// - **パス**: /api/private/history/{id}
+/// - **認証**: 必要
+///
+/// ### エラー時
+/// - **404**: 履歴が存在しない
+pub async fn delete(
+    claims: Claims,
+    Path(id): Path<String>,
+    State(db): State<Arc<Database>>,
+) -> Response {
+    if !claims.is_ok() {
+        return unauthorized();
+    }
+
+    if let Err(res) = find_own(&db, &claims, &id).await {
+        return res;
+    }
+    if let Err(e) = common::history::delete(&db, &id).await {
+        return error_response(StatusCode::INTERNAL_SERVER_ERROR, e);
+    }
+    info!("history deleted: {}", id);
+
+    response_handler(StatusCode::OK, "success".to_string(), None, None).into_response()
+}
+
+/// # fill
+///
+/// mail の結果のメール文章の変数 (`{{変数名}}`) に値を差し込む
+/// 文頭の変数の一覧 (`{{変数名}}: 説明`) は取り除いて本文のみを返す
+///
+/// ## HTTP情報
+///
+/// - **メソッド**: POST
+/// - **パス**: /api/private/history/{id}/fill
+/// - **認証**: 必要
+///
+/// ## パラメータ
+///
+/// - `id`: 履歴のID (`switcher` のレスポンスの `id`)
+///
+/// ## ペイロード
+///
+/// ```json
+/// {
+///   "values": {"会社名": "株式会社サンプル", "担当者名": "山田"}
+/// }
+/// ```
+///
+/// - `values`: 任意。変数名をキーとする文字列。空の値は指定されていないものとして扱う
+///
+/// ## レスポンス
+///
+/// ```json
+/// {
+///   "message": "success",
+///   "data": {
+///     "id": "3f0c...",
+///     "body": "株式会社サンプル\n山田様\n\n...{{日付}}...",
+///     "placeholders": [{"name": "会社名", "description": "クライアントの会社名", "occurrences": 2}],
+///     "unfilled": ["日付"],
+///     "unused": []
+///   }
+/// }
+/// ```
+///
+/// - `unfilled`: 値が指定されず本文に残った変数
+/// - `unused`: メール文章にない変数の値
+///
+/// ### エラー時
+/// - **400**: mail 以外・失敗したリクエストの履歴、`values` の誤り
+/// - **404**: 履歴が存在しない
+pub async fn fill(
+    claims: Claims,
+    Path(id): Path<String>,
+    State(db): State<Arc<Database>>,
+    Json(body): Json<Value>,
+) -> Response {
+    if !claims.is_ok() {
+        return unauthorized();
+    }
+
+    let values = match body.get("values") {
+        Some(v) if !v.is_null() => {
+            match serde_json::from_value::<HashMap<String, String>>(v.clone()) {
+                Ok(v) => v,
+                Err(e) => {
+                    return error_response(
+                        StatusCode::BAD_REQUEST,
+                        format!("invalid values: {}", e),
+                    );
+                }
+            }
+        }
+        _ => HashMap::new(),
+    };
+    let history = match find_own(&db, &claims, &id).await {
+        Ok(v) => v,
+        Err(res) => return res,
+    };
+    if history.prompt_type != "mail" {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            format!("placeholders are not supported: {}", history.prompt_type),
+        );
+    }
+    if history.error.is_some() {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            format!("history has no output: {}", history.id),
+        );
+    }
+
+    let mail = MailRefactoring::parse(&history.output);
+    let mut data = json!(fill_placeholders(&mail.refactored_body, &values));
+    data["id"] = json!(history.id);
+
+    response_handler(StatusCode::OK, "success".to_string(), Some(data), None).into_response()
+}
@@ -0,0 +1,470 @@
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    Json,
+    extract::{Path, State},
+    response::{IntoResponse, Response},
+};
+use log::{error, info};
+use reqwest::StatusCode;
+use serde_json::Value;
+use tokio::sync::mpsc;
+
+use crate::{
+    api::{
+        checker::{
+            PathParams, Update, build_request, execute, resolve_template, save_failure,
+            save_outcome,
+        },
+        utils::{error_response, response_handler, unauthorized},
+    },
+    common::{
+        self,
+        database::Database,
+        http::Http,
+        job::JobQueue,
+        provider::{AiError, AiRequest},
+    },
+    models::{
+        claim::Claims,
+        job::{Job, JobError, JobProgress, JobStatus},
+        template::TemplateVersion,
+    },
+};
+
+// Firestore のドキュメントの上限 (1MiB) に収まるよう、リクエストボディの大きさを制限する
+// 完了時のドキュメントには入力と出力が何度も含まれる (出力・markdown・HTML・差分とそのHTMLなど) ため、
+// 出力が入力と同程度の長さになる場合に、入力の約10倍で上限に収まる大きさとする
+const MAX_BODY_BYTES: usize = 100_000;
+// 実行中の部分テキスト・処理状況を保存する間隔
+const SAVE_INTERVAL: Duration = Duration::from_secs(2);
+// 変化がなくても実行中であることを記録する間隔
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+// 更新がこの秒数を超えて途絶えたジョブは中断されたものとみなす
+const STALE_SECONDS: i64 = 300;
+
+fn ai_error_response(err: AiError) -> Response {
+    response_handler(
+        err.status(),
+        err.code().to_string(),
+        None,
+        Some(err.to_string()),
+    )
+    .into_response()
+}
+
+// ジョブのテンプレートとAIへのリクエストを用意する
+async fn prepare(db: &Database, job: &Job) -> Result<(TemplateVersion, AiRequest), AiError> {
+    let message = job.body["message"].as_str().unwrap_or_default();
+    if message.is_empty() {
+        return Err(AiError::BadRequest("message is empty".to_string()));
+    }
+    let template = resolve_template(db, &job.user_id, &job.prompt_type).await?;
+    let request = build_request(&template, message, &job.body)?;
+    Ok((template, request))
+}
+
+// 作成者以外には存在しないものとして扱う
+async fn find_own(db: &Database, claims: &Claims, id: &str) -> Result<Job, Response> {
+    match common::job::get(db, id).await {
+        Ok(Some(job)) if job.user_id == claims.user_id => Ok(job),
+        Ok(_) => Err(error_response(
+            StatusCode::NOT_FOUND,
+            format!("job not found: {}", id),
+        )),
+        Err(e) => Err(error_response(StatusCode::INTERNAL_SERVER_ERROR, e)),
+    }
+}
+
+/// # create
+///
+/// AIへのリクエストをジョブとして受け付け、ジョブIDをすぐに返す
+/// 処理に時間のかかる監査や長い文字起こしで、HTTPのタイムアウトを避けるために使う
+///
+/// ## HTTP情報
+///
+/// - **メソッド**: POST
+/// - **パス**: /api/private/jobs/{target_ai}/{prompt_type}
+/// - **認証**: 必要
+///
+/// ## パラメータ・ペイロード
+///
+/// `switcher` と同じ
+///
+/// ## レスポンス
+///
+/// ```json
+/// {
+///   "message": "success",
+///   "data": {"id": "3f0c...", "status": "queued", "partial": "", "progress": null, "result": null, "error": null, ...}
+/// }
+/// ```
+///
+/// ### エラー時
+/// - **400 / 404**: `switcher` と同じ入力の誤り
+/// - **413**: リクエストボディが大きすぎる
+pub async fn create(
+    claims: Claims,
+    Path(path_params): Path<PathParams>,
+    State(db): State<Arc<Database>>,
+    State(http): State<Http>,
+    State(jobs): State<Arc<JobQueue>>,
+    Json(body): Json<Value>,
+) -> Response {
+    if !claims.is_ok() {
+        return unauthorized();
+    }
+    info!("claims: {:?}", claims);
+
+    let size = body.to_string().len();
+    if size > MAX_BODY_BYTES {
+        return error_response(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("body is too large: {} bytes", size),
+        );
+    }
+
+    let job = Job::new(
+        &claims.user_id,
+        &path_params.target_ai,
+        &path_params.prompt_type,
+        body,
+    );
+    // 入力の誤りは受け付け時に返す
+    if let Err(err) = prepare(&db, &job).await {
+        return ai_error_response(err);
+    }
+    if let Err(e) = common::job::create(&db, &job).await {
+        return error_response(StatusCode::INTERNAL_SERVER_ERROR, e);
+    }
+    info!("job queued: {}", job.id);
+
+    let status = job.to_status();
+    jobs.spawn(
+        &job.id,
+        keep_queued(db.clone(), job.id.clone()),
+        run(db, http, job.id.clone()),
+    );
+
+    response_handler(
+        StatusCode::ACCEPTED,
+        "success".to_string(),
+        Some(status),
+        None,
+    )
+    .into_response()
+}
+
+/// # get
+///
+/// ジョブの状態を返す
+///
+/// ## HTTP情報
+///
+/// - **メソッド**: GET
+/// - **パス**: /api/private/jobs/{id}
+/// - **認証**: 必要
+///
+/// ## レスポンス
+///
+/// ```json
+/// {
+///   "message": "success",
+///   "data": {
+///     "id": "3f0c...",
+///     "target_ai": "gemini",
+///     "prompt_type": "integrity",
+///     "status": "running",
+///     "partial": "(実行中の部分テキスト markdown)",
+///     "progress": {"stage": "map", "done": 2, "total": 5},
+///     "result": null,
+///     "error": null,
+///     "created_at": "...",
+///     "updated_at": "..."
+///   }
+/// }
+/// ```
+///
+/// - `status`: [queued, running, succeeded, failed, cancelled]
+/// - `partial`: 実行中の部分テキスト (Gemini のみ)
+/// - `progress`: 長い文字起こしを分割して要約する場合の処理状況
+/// - `result`: 成功時。`switcher` の成功時の data と同じ
+/// - `error`: 失敗時。`{"code": "safety", "message": "エラーメッセージ"}`
+///   `code` が `save` の場合は結果を保存できなかった。結果は履歴に保存されている場合がある
+///
+/// ### エラー時
+/// - **404**: ジョブが存在しない
+pub async fn get(
+    claims: Claims,
+    Path(id): Path<String>,
+    State(db): State<Arc<Database>>,
+) -> Response {
+    if !claims.is_ok() {
+        return unauthorized();
+    }
+
+    match find_own(&db, &claims, &id).await {
+        Ok(job) => response_handler(
+            StatusCode::OK,
+            "success".to_string(),
+            Some(job.to_status()),
+            None,
+        )
+        .into_response(),
+        Err(res) => res,
+    }
+}
+
+/// # cancel
+///
+/// 実行待ち・実行中のジョブを取り消す
+///
+/// ## HTTP情報
+///
+/// - **メソッド**: DELETE
+/// - **パス**: /api/private/jobs/{id}
+/// - **認証**: 必要
+///
+/// ### エラー時
+/// - **404**: ジョブが存在しない
+/// - **409**: ジョブが既に終了している
+pub async fn cancel(
+    claims: Claims,
+    Path(id): Path<String>,
+    State(db): State<Arc<Database>>,
+    State(jobs): State<Arc<JobQueue>>,
+) -> Response {
+    if !claims.is_ok() {
+        return unauthorized();
+    }
+
+    let job = match find_own(&db, &claims, &id).await {
+        Ok(job) => job,
+        Err(res) => return res,
+    };
+    if job.status.is_finished() {
+        return error_response(
+            StatusCode::CONFLICT,
+            format!("job is already {}", job.status.as_str()),
+        );
+    }
+
+    // 他のインスタンスで実行中の場合は、実行側が取り消しを検知して中断する
+    jobs.cancel(&id).await;
+    let job = match common::job::cancel(&db, &id).await {
+        Ok(Some(job)) => job,
+        // 中断を待つ間に終了した
+        Ok(None) => {
+            return match find_own(&db, &claims, &id).await {
+                Ok(job) => error_response(
+                    StatusCode::CONFLICT,
+                    format!("job is already {}", job.status.as_str()),
+                ),
+                Err(res) => res,
+            };
+        }
+        Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+    };
+    info!("job cancelled: {}", id);
+
+    response_handler(
+        StatusCode::OK,
+        "success".to_string(),
+        Some(job.to_status()),
+        None,
+    )
+    .into_response()
+}
+
+/// 再起動などで中断されたジョブを再開する
+/// 更新が途絶えているもののみを対象とし、他のインスタンスで実行中・実行待ちのジョブは再開しない
+pub async fn resume(db: Arc<Database>, http: Http, jobs: Arc<JobQueue>) {
+    let unfinished = match common::job::unfinished(&db).await {
+        Ok(v) => v,
+        Err(e) => {
+            error!("Failed to read jobs: {}", e);
+            return;
+        }
+    };
+    for job in unfinished
+        .into_iter()
+        .filter(|job| job.is_stale(STALE_SECONDS))
+    {
+        info!("job resumed: {}", job.id);
+        jobs.spawn(
+            &job.id,
+            keep_queued(db.clone(), job.id.clone()),
+            run(db.clone(), http.clone(), job.id.clone()),
+        );
+    }
+}
+
+// 実行枠を待つ間、実行待ちであることを定期的に記録する
+// 待ち時間が長くても、中断されたジョブとして他のインスタンスに再開されないようにする
+async fn keep_queued(db: Arc<Database>, id: String) {
+    let mut interval = tokio::time::interval(HEARTBEAT_INTERVAL);
+    // 最初の tick はすぐに完了する
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        match common::job::touch(&db, &id).await {
+            Ok(true) => (),
+            Ok(false) => return,
+            Err(e) => error!("Failed to save job {}: {}", id, e),
+        }
+    }
+}
+
+// 実行中の状態を保存する
+// 取り消しの確認と保存は1つのトランザクションで行い、取り消された場合は Ok(false) を返して実行を中断させる
+async fn checkpoint(db: &Database, job: &mut Job) -> Result<bool, String> {
+    let saved = common::job::save(db, job).await?;
+    if !saved {
+        info!("job cancelled while running: {}", job.id);
+    }
+    Ok(saved)
+}
+
+// 結果を保存できなかった (ドキュメントの上限を超えたなど) ジョブを、結果を除いて失敗として記録する
+// 実行中のまま残ると、再開時に同じリクエストでAIを再度呼び出してしまう
+async fn fail_to_save(db: &Database, job: &mut Job, err: String) {
+    error!("Failed to save job {}: {}", job.id, err);
+    // 結果は履歴に保存されている
+    let message = match job.result.as_ref().and_then(|r| r["id"].as_str()) {
+        Some(history_id) => format!("failed to save the result, see history: {}", history_id),
+        None => format!("failed to save the result: {}", err),
+    };
+    job.status = JobStatus::Failed;
+    job.partial.clear();
+    job.progress = None;
+    job.result = None;
+    job.error = Some(JobError {
+        code: "save".to_string(),
+        message,
+    });
+    if let Err(e) = checkpoint(db, job).await {
+        error!("Failed to save job {}: {}", job.id, e);
+    }
+}
+
+fn apply(job: &mut Job, update: Update) {
+    match update {
+        Update::Chunk(text) => job.partial.push_str(&text),
+        Update::Progress(progress) => {
+            job.progress = Some(JobProgress {
+                stage: progress.stage.to_string(),
+                done: progress.done,
+                total: progress.total,
+            })
+        }
+    }
+}
+
+/// ジョブを実行し、状態と結果をデータベースに保存する
+async fn run(db: Arc<Database>, http: Http, id: String) {
+    // 実行枠を待つ間に取り消された、または他のインスタンスが実行を始めたジョブは実行しない
+    let mut job = match common::job::start(&db, &id, STALE_SECONDS).await {
+        Ok(Some(job)) => job,
+        Ok(None) => return,
+        Err(e) => {
+            error!("Failed to start job {}: {}", id, e);
+            return;
+        }
+    };
+    info!("job started: {}", job.id);
+
+    let outcome = match prepare(&db, &job).await {
+        Ok((template, request)) => {
+            let target_ai = job.target_ai.clone();
+            let body = job.body.clone();
+            let message = body["message"].as_str().unwrap_or_default();
+            let (tx, mut rx) = mpsc::channel::<Update>(32);
+            let start = std::time::Instant::now();
+            let execution = execute(
+                &http,
+                &target_ai,
+                &template,
+                message,
+                &body,
+                &request,
+                Some(tx),
+            );
+            tokio::pin!(execution);
+
+            let mut interval = tokio::time::interval(SAVE_INTERVAL);
+            let mut changed = false;
+            let mut last_saved = std::time::Instant::now();
+            let outcome = loop {
+                tokio::select! {
+                    outcome = &mut execution => break outcome,
+                    Some(update) = rx.recv() => {
+                        apply(&mut job, update);
+                        changed = true;
+                    }
+                    _ = interval.tick() => {
+                        if changed || last_saved.elapsed() >= HEARTBEAT_INTERVAL {
+                            match checkpoint(&db, &mut job).await {
+                                Ok(true) => (),
+                                Ok(false) => return,
+                                Err(e) => error!("Failed to save job {}: {}", job.id, e),
+                            }
+                            changed = false;
+                            last_saved = std::time::Instant::now();
+                        }
+                    }
+                }
+            };
+            while let Ok(update) = rx.try_recv() {
+                apply(&mut job, update);
+            }
+            match outcome {
+                Ok(outcome) => Ok(save_outcome(
+                    &db,
+                    &job.user_id,
+                    &target_ai,
+                    &template,
+                    &body,
+                    &request,
+                    &outcome,
+                )
+                .await),
+                Err(err) => {
+                    save_failure(
+                        &db,
+                        &job.user_id,
+                        &target_ai,
+                        &template,
+                        message,
+                        &err,
+                        start,
+                    )
+                    .await;
+                    Err(err)
+                }
+            }
+        }
+        Err(err) => Err(err),
+    };
+
+    match outcome {
+        Ok(result) => {
+            job.status = JobStatus::Succeeded;
+            job.result = Some(result);
+            // 部分テキストは結果の出力と同じため、ドキュメントを小さくするために残さない
+            job.partial.clear();
+        }
+        Err(err) => {
+            error!("job failed: {}, {}", job.id, err);
+            job.status = JobStatus::Failed;
+            job.error = Some(JobError {
+                code: err.code().to_string(),
+                message: err.to_string(),
+            });
+        }
+    }
+    match checkpoint(&db, &mut job).await {
+        Ok(true) => info!("job finished: {}, {}", job.id, job.status.as_str()),
+        Ok(false) => (),
+        Err(e) => fail_to_save(&db, &mut job, e).await,
+    }
+}
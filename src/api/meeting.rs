@@ -0,0 +1,303 @@
+use axum::{
+    Json,
+    extract::Query,
+    http::header,
+    response::{IntoResponse, Response},
+};
+use chrono::NaiveDate;
+use log::info;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use tokio::sync::mpsc;
+
+use crate::{
+    api::{
+        checker::{INPUT_RULE, build_request},
+        utils::{response_handler, unauthorized},
+    },
+    common::{
+        export::{Export, resolve_date},
+        http::Http,
+        provider::{self, AiError, AiRequest, AiResponse, Usage},
+        render::fence,
+        tokens,
+    },
+    models::{claim::Claims, meeting::MeetingMinutes, template::TemplateVersion},
+};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ExportQuery {
+    // [json, ics, csv]
+    pub format: Option<String>,
+}
+
+/// # export
+///
+/// 構造化された議事録 (`structured: true` の出力) の ToDo と次回予定を
+/// カレンダー (.ics) や CSV として書き出す
+///
+/// ## HTTP情報
+///
+/// - **メソッド**: POST
+/// - **パス**: /api/private/meeting/export
+/// - **認証**: 必要
+///
+/// ## クエリパラメータ
+///
+/// - `format`: [json, ics, csv] 省略時は json
+///
+/// ## ペイロード
+///
+/// ```json
+/// {
+///   "minutes": {"タイトル": "定例会議", "ToDoリスト(アクションアイテム)": [...], "次回予定": {...}, ...},
+///   "meeting_date": "2025-04-02"
+/// }
+/// ```
+///
+/// - `minutes`: 議事録の構造化データ
+/// - `meeting_date`: 任意。相対的な期限 (「来週金曜」など) の基準日。省略時は議事録の「日時」を使う
+///
+/// ## レスポンス
+///
+/// - `ics`: `text/calendar` の添付ファイル。期限を解釈できなかった ToDo は含まない
+/// - `csv`: `text/csv` の添付ファイル。期限を解釈できなかった行は「要確認」列に印を付ける
+/// - `json`: 書き出す内容の確認用
+///
+/// ```json
+/// {
+///   "message": "success",
+///   "data": {
+///     "title": "定例会議",
+///     "meeting_date": "2025-04-02",
+///     "unresolved": 1,
+///     "items": [
+///       {"kind": "todo", "assignee": "佐藤 花子", "content": "見積もり作成", "due_text": "来週金曜",
+///        "date": "2025-04-11", "start": null, "end": null, "needs_review": false}
+///     ]
+///   }
+/// }
+/// ```
+///
+/// ### エラー時
+/// - **400**: 議事録の形式の誤り、会議の日付が不明、未対応の形式
+pub async fn export(
+    claims: Claims,
+    Query(query): Query<ExportQuery>,
+    Json(body): Json<Value>,
+) -> Response {
+    if !claims.is_ok() {
+        return unauthorized();
+    }
+    info!("claims: {:?}", claims);
+
+    let minutes = match serde_json::from_value::<MeetingMinutes>(body["minutes"].clone()) {
+        Ok(v) => v,
+        Err(e) => {
+            return response_handler(
+                StatusCode::BAD_REQUEST,
+                "error".to_string(),
+                None,
+                Some(format!("invalid minutes: {}", e)),
+            )
+            .into_response();
+        }
+    };
+
+    // 基準日は指定された日付、なければ議事録の「日時」から読み取る
+    // 年を省略した日付は今日を基準に補う
+    let today = chrono::Local::now().date_naive();
+    let meeting_date: Option<NaiveDate> = body["meeting_date"]
+        .as_str()
+        .or(minutes.date.as_deref())
+        .and_then(|text| resolve_date(text, today));
+    let Some(meeting_date) = meeting_date else {
+        return response_handler(
+            StatusCode::BAD_REQUEST,
+            "error".to_string(),
+            None,
+            Some("meeting_date is required: 日時 of minutes could not be parsed".to_string()),
+        )
+        .into_response();
+    };
+
+    let export = Export::from_minutes(&minutes, meeting_date);
+    info!(
+        "export: {} items, {} unresolved",
+        export.items.len(),
+        export.unresolved()
+    );
+
+    match query.format.as_deref().unwrap_or("json") {
+        "ics" => (
+            [
+                (header::CONTENT_TYPE, "text/calendar; charset=utf-8"),
+                (
+                    header::CONTENT_DISPOSITION,
+                    "attachment; filename=\"meeting.ics\"",
+                ),
+            ],
+            export.to_ics(),
+        )
+            .into_response(),
+        "csv" => (
+            [
+                (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+                (
+                    header::CONTENT_DISPOSITION,
+                    "attachment; filename=\"meeting_todo.csv\"",
+                ),
+            ],
+            export.to_csv(),
+        )
+            .into_response(),
+        "json" => response_handler(
+            StatusCode::OK,
+            "success".to_string(),
+            Some(json!({
+                "title": export.title,
+                "meeting_date": export.meeting_date,
+                "unresolved": export.unresolved(),
+                "items": export.items,
+            })),
+            None,
+        )
+        .into_response(),
+        format => response_handler(
+            StatusCode::BAD_REQUEST,
+            "error".to_string(),
+            None,
+            Some(format!("format is not supported: {}", format)),
+        )
+        .into_response(),
+    }
+}
+
+/// 長い文字起こしの処理状況
+/// map: 分割した文字起こしごとの構造化、reduce: 結果の統合
+#[derive(Debug, Clone, Serialize)]
+pub struct Progress {
+    pub stage: &'static str,
+    pub done: usize,
+    pub total: usize,
+}
+
+/// 分割して要約した結果
+#[derive(Debug, Clone)]
+pub struct MapReduce {
+    pub response: AiResponse,
+    pub minutes: MeetingMinutes,
+    pub chunks: usize,
+}
+
+impl MapReduce {
+    /// markdown の議事録と、指定された場合は構造化データを返す
+    pub fn render(&self, structured: bool) -> (String, Option<Value>) {
+        (
+            self.minutes.to_markdown(),
+            structured.then(|| json!(self.minutes)),
+        )
+    }
+}
+
+/// 文字起こしがモデルの1回のリクエストに収まらないか
+pub fn needs_chunking(target_ai: &str, prompt_type: &str, message: &str) -> bool {
+    prompt_type == "meeting"
+        && tokens::estimate(target_ai, message) > tokens::chunk_budget(target_ai)
+}
+
+/// 長い文字起こしを分割して構造化し (map)、最後に1つの議事録に統合する (reduce)
+/// 各段階の終了時に `progress` へ処理状況を送る
+pub async fn map_reduce(
+    http: &Http,
+    target_ai: &str,
+    template: &TemplateVersion,
+    message: &str,
+    body: &Value,
+    progress: Option<&mpsc::Sender<Progress>>,
+) -> Result<MapReduce, AiError> {
+    let chunks = tokens::split(message, tokens::chunk_budget(target_ai), target_ai);
+    let total = chunks.len();
+    info!("meeting map-reduce: {} chunks", total);
+
+    let report = |stage: &'static str, done: usize, total: usize| async move {
+        if let Some(tx) = progress {
+            let _ = tx.send(Progress { stage, done, total }).await;
+        }
+    };
+
+    // 分割した文字起こしはそれぞれ構造化データとして出力させる
+    let mut map_body = body.clone();
+    map_body["structured"] = json!(true);
+
+    let mut parts = Vec::new();
+    let mut usage: Option<Usage> = None;
+    let mut config = None;
+    for (i, chunk) in chunks.iter().enumerate() {
+        let mut request = build_request(template, chunk, &map_body)?;
+        request.system = request
+            .system
+            .map(|system| format!("{}\n\n{}", system, chunk_rule(i + 1, total)));
+        let response = provider::request(http, target_ai, &request).await?;
+        parts.push(MeetingMinutes::parse(&response.result).map_err(AiError::Upstream)?);
+        add_usage(&mut usage, response.usage.as_ref());
+        config.get_or_insert(request.config);
+        report("map", i + 1, total).await;
+    }
+
+    // 重複の除去は機械的に行い、要約の統合と表記の揺れはモデルに任せる
+    let merged = MeetingMinutes::merge(parts);
+    let merged = serde_json::to_string_pretty(&merged)
+        .map_err(|e| AiError::Upstream(format!("failed to serialize minutes: {}", e)))?;
+    let request = AiRequest::new(
+        format!("## 部分ごとの議事録\n{}", fence(&merged)),
+        config.unwrap_or_default(),
+    )
+    .with_system(format!("{}\n\n{}", REDUCE_SYSTEM, INPUT_RULE))
+    .with_response_schema(MeetingMinutes::schema());
+    let mut response = provider::request(http, target_ai, &request).await?;
+    let mut minutes = MeetingMinutes::parse(&response.result).map_err(AiError::Upstream)?;
+    minutes.dedup();
+    report("reduce", 1, 1).await;
+
+    add_usage(&mut usage, response.usage.as_ref());
+    response.usage = usage;
+    Ok(MapReduce {
+        response,
+        minutes,
+        chunks: total,
+    })
+}
+
+fn add_usage(total: &mut Option<Usage>, usage: Option<&Usage>) {
+    if let Some(usage) = usage {
+        total.get_or_insert_with(Usage::default).add(usage);
+    }
+}
+
+/// 分割した文字起こしの何番目かを伝える
+fn chunk_rule(index: usize, total: usize) -> String {
+    format!(
+        r##"## 分割入力 (Chunked Input)
+文字起こしテキストは長いため {total} 個に分割されており、これは {index}/{total} 番目です。
+この部分に含まれる内容のみを構造化してください。前後の部分にある内容を推測して補わないでください。"##
+    )
+}
+
+/// 分割して構造化した議事録を統合する指示
+const REDUCE_SYSTEM: &str = r##"# 議事録の統合
+
+## あなたの役割 (AI Role)
+あなたは、議事録作成の専門家です。
+
+## 目的 (Goal)
+長い文字起こしテキストを分割して作成した議事録の構造化データ (JSON) が、ユーザーメッセージに1つにまとめて渡されます。これを1つのミーティングの議事録として統合してください。
+
+## 統合のルール
+*   「要約」は部分ごとの要約をつなげたものです。ミーティング全体の要約として書き直してください。
+*   「議題」「決定事項」「ToDoリスト(アクションアイテム)」「合意事項」「提起された課題・懸念点」で、表現が異なるだけで同じ内容の項目は1つにまとめてください。ToDo は担当者と期限が同じ場合に限り統合し、異なる場合は別の項目として残してください。
+*   後の部分で変更・撤回された決定事項は、最終的な内容のみを残してください。
+*   データにない情報を追加しないでください。不明な項目は null としてください。
+*   指定されたスキーマに沿った JSON だけを出力してください。"##;
@@ -1,5 +1,11 @@
 pub mod checker;
+pub mod conversation;
 pub mod data;
+pub mod history;
 pub mod initial;
+pub mod job;
+pub mod meeting;
+pub mod template;
 pub mod user;
+pub mod user_template;
 pub mod utils;
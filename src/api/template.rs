@@ -0,0 +1,408 @@
+use std::sync::Arc;
+
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    response::{IntoResponse, Response},
+};
+use log::info;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use similar::TextDiff;
+
+use crate::{
+    api::{
+        checker::builtin_template,
+        utils::{error_response, response_handler, unauthorized},
+    },
+    common::{self, database::Database},
+    models::{
+        claim::Claims,
+        prompt,
+        template::{
+            BUILTIN_VERSION, PromptTemplate, TemplateInput, TemplateVersion, is_valid_name,
+        },
+    },
+};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DiffQuery {
+    pub from: u32,
+    pub to: u32,
+}
+
+// 管理者以外は 403 を返す
+fn forbidden(claims: &Claims) -> Option<Response> {
+    if !claims.is_ok() {
+        return Some(unauthorized());
+    }
+    if !claims.is_admin() {
+        return Some(
+            response_handler(
+                StatusCode::FORBIDDEN,
+                "forbidden".to_string(),
+                None,
+                Some("admin only".to_string()),
+            )
+            .into_response(),
+        );
+    }
+    info!("admin: {}", claims.user_id);
+    None
+}
+
+// 指定したバージョンを取得する。0 は組み込みのテンプレート
+async fn find_version(
+    db: &Database,
+    name: &str,
+    version: u32,
+) -> Result<Option<TemplateVersion>, String> {
+    if version == BUILTIN_VERSION {
+        return Ok(builtin_template(name));
+    }
+    common::template::version(db, name, version).await
+}
+
+/// # list
+///
+/// 登録されているテンプレートと有効なバージョンの一覧を返す
+/// 組み込みのテンプレートのうちデータベースに未登録のものは version 0 として含める
+///
+/// ## HTTP情報
+///
+/// - **メソッド**: GET
+/// - **パス**: /api/private/admin/prompts
+/// - **認証**: 必要 (管理者)
+///
+/// ## レスポンス
+///
+/// ```json
+/// {
+///   "message": "success",
+///   "data": [{"name": "mail", "active_version": 2, "latest_version": 3, "updated_by": "admin", "updated_at": "..."}]
+/// }
+/// ```
+pub async fn list(claims: Claims, State(db): State<Arc<Database>>) -> Response {
+    if let Some(res) = forbidden(&claims) {
+        return res;
+    }
+
+    let mut templates = match common::template::list(&db).await {
+        Ok(v) => v,
+        Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+    };
+    for prompt in prompt::builtin() {
+        if !templates.iter().any(|t| t.name == prompt.name) {
+            templates.push(PromptTemplate {
+                name: prompt.name,
+                ..Default::default()
+            });
+        }
+    }
+    templates.sort_by(|a, b| a.name.cmp(&b.name));
+
+    response_handler(
+        StatusCode::OK,
+        "success".to_string(),
+        Some(json!(templates)),
+        None,
+    )
+    .into_response()
+}
+
+/// # get
+///
+/// テンプレートの有効なバージョンと全バージョンを返す
+///
+/// ## HTTP情報
+///
+/// - **メソッド**: GET
+/// - **パス**: /api/private/admin/prompts/{name}
+/// - **認証**: 必要 (管理者)
+///
+/// ## レスポンス
+///
+/// ```json
+/// {
+///   "message": "success",
+///   "data": {
+///     "template": {"name": "mail", "active_version": 2, "latest_version": 3, ...},
+///     "versions": [{"name": "mail", "version": 1, "description": "...", "system": "...", "user": "...", ...}]
+///   }
+/// }
+/// ```
+///
+/// ### エラー時
+/// - **404**: テンプレートが存在しない
+pub async fn get(
+    claims: Claims,
+    Path(name): Path<String>,
+    State(db): State<Arc<Database>>,
+) -> Response {
+    if let Some(res) = forbidden(&claims) {
+        return res;
+    }
+
+    let template = match common::template::get(&db, &name).await {
+        Ok(v) => v,
+        Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+    };
+    let mut versions = match common::template::versions(&db, &name).await {
+        Ok(v) => v,
+        Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+    };
+    let builtin = builtin_template(&name);
+    if template.is_none() && builtin.is_none() {
+        return error_response(
+            StatusCode::NOT_FOUND,
+            format!("template not found: {}", name),
+        );
+    }
+    if let Some(builtin) = builtin {
+        versions.insert(0, builtin);
+    }
+
+    response_handler(
+        StatusCode::OK,
+        "success".to_string(),
+        Some(json!({
+            "template": template,
+            "versions": versions,
+        })),
+        None,
+    )
+    .into_response()
+}
+
+/// # create
+///
+/// テンプレートの新しいバージョンを作成する
+/// 既存のバージョンは変更できないため、文言の変更は常に新しいバージョンとして登録する
+///
+/// ## HTTP情報
+///
+/// - **メソッド**: POST
+/// - **パス**: /api/private/admin/prompts/{name}
+/// - **認証**: 必要 (管理者)
+///
+/// ## ペイロード
+///
+/// ```json
+/// {
+///   "description": "クライアントに対するメール構文のリファクタリング",
+///   "system": "# 指示 ...",
+///   "user": "## 元のメール文章\n{{input}}",
+///   "note": "敬語の指示を追加",
+///   "activate": true
+/// }
+/// ```
+///
+/// - `user`: `{{input}}` の位置にユーザーの入力を差し込む
+/// - `activate`: 任意。既定は true。false の場合は有効なバージョンを変更しない
+///
+/// ## レスポンス
+///
+/// ```json
+/// {"message": "success", "data": {"template": {...}, "version": {...}}}
+/// ```
+///
+/// ### エラー時
+/// - **400**: テンプレート名・ペイロードの誤り
+pub async fn create(
+    claims: Claims,
+    Path(name): Path<String>,
+    State(db): State<Arc<Database>>,
+    Json(body): Json<Value>,
+) -> Response {
+    if let Some(res) = forbidden(&claims) {
+        return res;
+    }
+
+    if !is_valid_name(&name) {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            format!("invalid template name: {}", name),
+        );
+    }
+    let input = match serde_json::from_value::<TemplateInput>(body) {
+        Ok(v) => v,
+        Err(e) => return error_response(StatusCode::BAD_REQUEST, e.to_string()),
+    };
+    if let Err(e) = input.validate() {
+        return error_response(StatusCode::BAD_REQUEST, e);
+    }
+
+    match common::template::create(&db, &name, input, &claims.user_id).await {
+        Ok((template, version)) => {
+            info!(
+                "template created: {}, active: {}",
+                TemplateVersion::id(&name, version.version),
+                template.active_version
+            );
+            response_handler(
+                StatusCode::CREATED,
+                "success".to_string(),
+                Some(json!({
+                    "template": template,
+                    "version": version,
+                })),
+                None,
+            )
+            .into_response()
+        }
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
+
+/// # diff
+///
+/// 2つのバージョンの差分を unified diff 形式で返す
+///
+/// ## HTTP情報
+///
+/// - **メソッド**: GET
+/// - **パス**: /api/private/admin/prompts/{name}/diff
+/// - **認証**: 必要 (管理者)
+///
+/// ## クエリパラメータ
+///
+/// - `from`, `to`: 比較するバージョン。0 は組み込みのテンプレート
+///
+/// ## レスポンス
+///
+/// ```json
+/// {
+///   "message": "success",
+///   "data": {"from": 1, "to": 2, "description": null, "system": "--- mail@1\n+++ mail@2\n@@ ...", "user": ""}
+/// }
+/// ```
+///
+/// - 変更のない項目は空文字 (description は null)
+///
+/// ### エラー時
+/// - **404**: バージョンが存在しない
+pub async fn diff(
+    claims: Claims,
+    Path(name): Path<String>,
+    Query(query): Query<DiffQuery>,
+    State(db): State<Arc<Database>>,
+) -> Response {
+    if let Some(res) = forbidden(&claims) {
+        return res;
+    }
+
+    let mut found = Vec::new();
+    for version in [query.from, query.to] {
+        match find_version(&db, &name, version).await {
+            Ok(Some(v)) => found.push(v),
+            Ok(None) => {
+                return error_response(
+                    StatusCode::NOT_FOUND,
+                    format!(
+                        "template version not found: {}",
+                        TemplateVersion::id(&name, version)
+                    ),
+                );
+            }
+            Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+        }
+    }
+    let (from, to) = (&found[0], &found[1]);
+
+    let from_id = TemplateVersion::id(&name, from.version);
+    let to_id = TemplateVersion::id(&name, to.version);
+    let unified = |a: &str, b: &str| {
+        TextDiff::from_lines(a, b)
+            .unified_diff()
+            .context_radius(3)
+            .header(&from_id, &to_id)
+            .to_string()
+    };
+
+    response_handler(
+        StatusCode::OK,
+        "success".to_string(),
+        Some(json!({
+            "from": from.version,
+            "to": to.version,
+            "description": (from.description != to.description)
+                .then(|| json!({"from": from.description, "to": to.description})),
+            "system": unified(&from.system, &to.system),
+            "user": unified(&from.user, &to.user),
+        })),
+        None,
+    )
+    .into_response()
+}
+
+/// # rollback
+///
+/// 有効なバージョンを指定したバージョンに切り替える
+///
+/// ## HTTP情報
+///
+/// - **メソッド**: POST
+/// - **パス**: /api/private/admin/prompts/{name}/rollback
+/// - **認証**: 必要 (管理者)
+///
+/// ## ペイロード
+///
+/// ```json
+/// {"version": 1}
+/// ```
+///
+/// - `version`: 0 は組み込みのテンプレートに戻す
+///
+/// ## レスポンス
+///
+/// ```json
+/// {"message": "success", "data": {"name": "mail", "active_version": 1, "latest_version": 3, ...}}
+/// ```
+///
+/// ### エラー時
+/// - **404**: テンプレート・バージョンが存在しない
+pub async fn rollback(
+    claims: Claims,
+    Path(name): Path<String>,
+    State(db): State<Arc<Database>>,
+    Json(body): Json<Value>,
+) -> Response {
+    if let Some(res) = forbidden(&claims) {
+        return res;
+    }
+
+    let Some(version) = body["version"].as_u64().map(|v| v as u32) else {
+        return error_response(StatusCode::BAD_REQUEST, "version is required".to_string());
+    };
+    if version == BUILTIN_VERSION && builtin_template(&name).is_none() {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            format!("template has no builtin version: {}", name),
+        );
+    }
+
+    match common::template::activate(&db, &name, version, &claims.user_id).await {
+        Ok(Some(template)) => {
+            info!(
+                "template activated: {}",
+                TemplateVersion::id(&name, version)
+            );
+            response_handler(
+                StatusCode::OK,
+                "success".to_string(),
+                Some(json!(template)),
+                None,
+            )
+            .into_response()
+        }
+        Ok(None) => error_response(
+            StatusCode::NOT_FOUND,
+            format!(
+                "template version not found: {}",
+                TemplateVersion::id(&name, version)
+            ),
+        ),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
@@ -0,0 +1,301 @@
+use std::sync::Arc;
+
+use axum::{
+    Json,
+    extract::{Path, State},
+    response::{IntoResponse, Response},
+};
+use log::info;
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+
+use crate::{
+    api::{
+        checker::builtin_template,
+        utils::{error_response, response_handler, unauthorized},
+    },
+    common::{self, database::Database},
+    models::{
+        claim::Claims,
+        template::{UserTemplate, UserTemplateInput, is_valid_name},
+    },
+};
+
+// レスポンスには変数の一覧を含める
+fn to_json(template: &UserTemplate) -> Value {
+    let mut value = json!(template);
+    value["variables"] = json!(template.to_version().variables());
+    value
+}
+
+fn parse_input(body: Value) -> Result<UserTemplateInput, String> {
+    let input = serde_json::from_value::<UserTemplateInput>(body).map_err(|e| e.to_string())?;
+    input.validate()?;
+    Ok(input)
+}
+
+/// # list
+///
+/// ログインユーザーの非公開テンプレートの一覧を返す
+///
+/// ## HTTP情報
+///
+/// - **メソッド**: GET
+/// - **パス**: /api/private/templates
+/// - **認証**: 必要
+///
+/// ## レスポンス
+///
+/// ```json
+/// {
+///   "message": "success",
+///   "data": [{"name": "sales_mail", "version": 2, "description": "...", "system": "...", "user": "...", "variables": ["部署名"], ...}]
+/// }
+/// ```
+pub async fn list(claims: Claims, State(db): State<Arc<Database>>) -> Response {
+    if !claims.is_ok() {
+        return unauthorized();
+    }
+
+    match common::template::list_user(&db, &claims.user_id).await {
+        Ok(templates) => response_handler(
+            StatusCode::OK,
+            "success".to_string(),
+            Some(json!(templates.iter().map(to_json).collect::<Vec<Value>>())),
+            None,
+        )
+        .into_response(),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
+
+/// # get
+///
+/// ログインユーザーの非公開テンプレートを返す
+///
+/// ## HTTP情報
+///
+/// - **メソッド**: GET
+/// - **パス**: /api/private/templates/{name}
+/// - **認証**: 必要
+///
+/// ### エラー時
+/// - **404**: テンプレートが存在しない
+pub async fn get(
+    claims: Claims,
+    Path(name): Path<String>,
+    State(db): State<Arc<Database>>,
+) -> Response {
+    if !claims.is_ok() {
+        return unauthorized();
+    }
+
+    match common::template::get_user(&db, &claims.user_id, &name).await {
+        Ok(Some(template)) => response_handler(
+            StatusCode::OK,
+            "success".to_string(),
+            Some(to_json(&template)),
+            None,
+        )
+        .into_response(),
+        Ok(None) => error_response(
+            StatusCode::NOT_FOUND,
+            format!("template not found: {}", name),
+        ),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
+
+/// # create
+///
+/// 非公開テンプレートを作成する
+/// 作成したテンプレートは `/api/private/ai/{target_ai}/{name}` で使用できる
+///
+/// ## HTTP情報
+///
+/// - **メソッド**: POST
+/// - **パス**: /api/private/templates/{name}
+/// - **認証**: 必要
+///
+/// ## ペイロード
+///
+/// ```json
+/// {
+///   "description": "営業部向けのメール添削",
+///   "system": "あなたは{{部署名}}のメール担当者です。...",
+///   "user": "## 宛先: {{宛先}}\n{{input}}"
+/// }
+/// ```
+///
+/// - `{{input}}`: ユーザーの入力 (`message`) を差し込む位置。`user` に1つだけ含める
+/// - `{{変数名}}`: 使用時に `variables` で値を指定する
+///
+/// ### エラー時
+/// - **400**: テンプレート名・ペイロードの誤り
+/// - **409**: 同じ名前のテンプレートが既にある (共有のプロンプトタイプを含む)
+pub async fn create(
+    claims: Claims,
+    Path(name): Path<String>,
+    State(db): State<Arc<Database>>,
+    Json(body): Json<Value>,
+) -> Response {
+    if !claims.is_ok() {
+        return unauthorized();
+    }
+
+    if !is_valid_name(&name) {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            format!("invalid template name: {}", name),
+        );
+    }
+    let input = match parse_input(body) {
+        Ok(v) => v,
+        Err(e) => return error_response(StatusCode::BAD_REQUEST, e),
+    };
+
+    // 共有のプロンプトタイプを置き換えられないよう、既にある名前では作成できない
+    let shared = match common::template::get(&db, &name).await {
+        Ok(v) => v.is_some() || builtin_template(&name).is_some(),
+        Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+    };
+    if shared {
+        return error_response(
+            StatusCode::CONFLICT,
+            format!("prompt_type already exists: {}", name),
+        );
+    }
+
+    let now = chrono::Utc::now().to_rfc3339();
+    let template = UserTemplate {
+        user_id: claims.user_id.clone(),
+        name: name.clone(),
+        version: 1,
+        description: input.description,
+        system: input.system,
+        user: input.user,
+        created_at: now.clone(),
+        updated_at: now,
+    };
+    // 同じ名前のテンプレートがある場合は作成に失敗する
+    if let Err(e) = common::template::save_user(&db, &template, true).await {
+        return error_response(StatusCode::CONFLICT, e);
+    }
+    info!(
+        "user template created: {}",
+        UserTemplate::id(&claims.user_id, &name)
+    );
+
+    response_handler(
+        StatusCode::CREATED,
+        "success".to_string(),
+        Some(to_json(&template)),
+        None,
+    )
+    .into_response()
+}
+
+/// # update
+///
+/// 非公開テンプレートを更新する。更新のたびに version が増える
+///
+/// ## HTTP情報
+///
+/// - **メソッド**: PUT
+/// - **パス**: /api/private/templates/{name}
+/// - **認証**: 必要
+///
+/// ## ペイロード
+///
+/// `create` と同じ
+///
+/// ### エラー時
+/// - **400**: ペイロードの誤り
+/// - **404**: テンプレートが存在しない
+pub async fn update(
+    claims: Claims,
+    Path(name): Path<String>,
+    State(db): State<Arc<Database>>,
+    Json(body): Json<Value>,
+) -> Response {
+    if !claims.is_ok() {
+        return unauthorized();
+    }
+
+    let input = match parse_input(body) {
+        Ok(v) => v,
+        Err(e) => return error_response(StatusCode::BAD_REQUEST, e),
+    };
+    let current = match common::template::get_user(&db, &claims.user_id, &name).await {
+        Ok(Some(v)) => v,
+        Ok(None) => {
+            return error_response(
+                StatusCode::NOT_FOUND,
+                format!("template not found: {}", name),
+            );
+        }
+        Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+    };
+
+    let template = UserTemplate {
+        version: current.version + 1,
+        description: input.description,
+        system: input.system,
+        user: input.user,
+        updated_at: chrono::Utc::now().to_rfc3339(),
+        ..current
+    };
+    if let Err(e) = common::template::save_user(&db, &template, false).await {
+        return error_response(StatusCode::INTERNAL_SERVER_ERROR, e);
+    }
+
+    response_handler(
+        StatusCode::OK,
+        "success".to_string(),
+        Some(to_json(&template)),
+        None,
+    )
+    .into_response()
+}
+
+/// # delete
+///
+/// 非公開テンプレートを削除する
+///
+/// ## HTTP情報
+///
+/// - **メソッド**: DELETE
+/// - **パス**: /api/private/templates/{name}
+/// - **認証**: 必要
+///
+/// ### エラー時
+/// - **404**: テンプレートが存在しない
+pub async fn delete(
+    claims: Claims,
+    Path(name): Path<String>,
+    State(db): State<Arc<Database>>,
+) -> Response {
+    if !claims.is_ok() {
+        return unauthorized();
+    }
+
+    match common::template::get_user(&db, &claims.user_id, &name).await {
+        Ok(Some(_)) => (),
+        Ok(None) => {
+            return error_response(
+                StatusCode::NOT_FOUND,
+                format!("template not found: {}", name),
+            );
+        }
+        Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+    if let Err(e) = common::template::delete_user(&db, &claims.user_id, &name).await {
+        return error_response(StatusCode::INTERNAL_SERVER_ERROR, e);
+    }
+    info!(
+        "user template deleted: {}",
+        UserTemplate::id(&claims.user_id, &name)
+    );
+
+    response_handler(StatusCode::OK, "success".to_string(), None, None).into_response()
+}
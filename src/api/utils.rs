@@ -1,4 +1,8 @@
-use axum::{Json, http::StatusCode, response::IntoResponse};
+use axum::{
+    Json,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
 use log::error;
 use serde_json::{Value, json};
 
@@ -24,6 +28,22 @@ pub fn response_handler(
     (code, Json(body))
 }
 
+// 認証に失敗した場合のレスポンス
+pub fn unauthorized() -> Response {
+    response_handler(
+        StatusCode::UNAUTHORIZED,
+        "unauthorized".to_string(),
+        None,
+        Some("Unauthorized".to_string()),
+    )
+    .into_response()
+}
+
+// エラーのレスポンス
+pub fn error_response(code: StatusCode, err: String) -> Response {
+    response_handler(code, "error".to_string(), None, Some(err)).into_response()
+}
+
 #[allow(unused)]
 // 漢字の文字数でソートする関数
 pub fn kanji_len(s: &str) -> usize {
@@ -0,0 +1,142 @@
+use log::{error, info};
+use serde_json::{Value, json};
+
+use crate::common::{
+    http::Http,
+    provider::{AiError, AiRequest, AiResponse, Provider, Usage, env_or},
+};
+
+/// Anthropic Messages API
+#[derive(Debug, Clone)]
+pub struct Claude {
+    http: Http,
+    base_url: String,
+    model: String,
+    token: String,
+}
+
+impl Claude {
+    pub fn new(http: Http, base_url: String, model: String, token: String) -> Self {
+        Self {
+            http,
+            base_url,
+            model,
+            token,
+        }
+    }
+
+    // 環境変数から設定を読み込む
+    pub fn from_env(http: Http) -> Result<Self, AiError> {
+        let model = env_or("CLAUDE_MODEL", "");
+        let token = env_or("ANTHROPIC_API_KEY", "");
+        if model.is_empty() || token.is_empty() {
+            return Err(AiError::Config(
+                "CLAUDE_MODEL or ANTHROPIC_API_KEY is empty".to_string(),
+            ));
+        }
+
+        let base_url = env_or("ANTHROPIC_BASE_URL", "https://api.anthropic.com");
+        Ok(Self::new(http, base_url, model, token))
+    }
+}
+
+impl Provider for Claude {
+    fn name(&self) -> &'static str {
+        "claude"
+    }
+
+    async fn request(&self, request: &AiRequest) -> Result<AiResponse, AiError> {
+        let url = format!("{}/v1/messages", self.base_url);
+
+        let config = &request.config;
+        let mut body = json!({
+            "model": self.model,
+            "max_tokens": config.max_output_tokens,
+            "messages": request
+                .turns()
+                .iter()
+                .map(|turn| json!({"role": turn.role.as_chat_role(), "content": turn.text}))
+                .collect::<Vec<Value>>()
+        });
+        // temperature と top_p は同時に指定できないため、既定では temperature のみ送信する
+        // top_p / top_k はリクエストで明示的に指定された場合のみ、temperature の代わりに送信する
+        let overrides = &config.overrides;
+        if overrides.top_p.is_none() && overrides.top_k.is_none() {
+            // Claude の temperature は 0.0 - 1.0
+            body["temperature"] = json!(config.temperature.min(1.0));
+        }
+        if let Some(top_p) = overrides.top_p {
+            body["top_p"] = json!(top_p);
+        }
+        if let Some(top_k) = overrides.top_k {
+            body["top_k"] = json!(top_k);
+        }
+        // 役割・指示は system パラメータで渡す
+        if let Some(system) = &request.system {
+            body["system"] = json!(system);
+        }
+
+        let builder = self
+            .http
+            .client
+            .post(url)
+            .header("x-api-key", &self.token)
+            .header("anthropic-version", "2023-06-01")
+            .json(&body);
+        let res = self.http.send(builder).await?;
+        if !res.status().is_success() {
+            return Err(Http::upstream_error(res).await);
+        }
+
+        let value = match res.json::<Value>().await {
+            Ok(v) => v,
+            Err(e) => {
+                error!("JSONのパースに失敗しました: {}", e);
+                return Err(AiError::from(e));
+            }
+        };
+        info!("{:?}", value);
+
+        // content 配列の text ブロックを連結する
+        let texts = value
+            .get("content")
+            .and_then(|content| content.as_array())
+            .map(|arr| {
+                arr.iter()
+                    .filter(|block| block.get("type").and_then(|t| t.as_str()) == Some("text"))
+                    .filter_map(|block| block.get("text").and_then(|t| t.as_str()))
+                    .collect::<Vec<&str>>()
+            });
+
+        // 出力上限による打ち切りは個別のエラーとして扱う
+        let stop_reason = value["stop_reason"].as_str().map(|s| s.to_string());
+        if stop_reason.as_deref() == Some("max_tokens") {
+            return Err(AiError::MaxTokens(
+                "response reached max_tokens".to_string(),
+            ));
+        }
+
+        match texts {
+            Some(texts) if !texts.is_empty() => Ok(AiResponse {
+                finish_reason: stop_reason,
+                usage: value.get("usage").map(|usage| {
+                    let input = usage["input_tokens"].as_u64().unwrap_or_default() as u32;
+                    let output = usage["output_tokens"].as_u64().unwrap_or_default() as u32;
+                    Usage {
+                        prompt_tokens: input,
+                        output_tokens: output,
+                        total_tokens: input + output,
+                    }
+                }),
+                ..AiResponse::new(
+                    value["model"].as_str().unwrap_or(&self.model).to_string(),
+                    texts.join(""),
+                )
+            }),
+            _ => Err(AiError::Upstream(format!(
+                "`content` にテキストが見つかりませんでした。 {}",
+                value
+            ))),
+        }
+    }
+}
@@ -0,0 +1,20 @@
+use crate::{common::database::Database, models::conversation::Conversation};
+
+// AIの結果に対する会話の保存・取得
+
+const CONVERSATIONS: &str = "conversations";
+
+pub async fn create(db: &Database, conversation: &Conversation) -> Result<(), String> {
+    db.create(CONVERSATIONS, &conversation.id, conversation.clone())
+        .await
+}
+
+pub async fn get(db: &Database, id: &str) -> Result<Option<Conversation>, String> {
+    db.read::<Conversation>(CONVERSATIONS, id).await
+}
+
+pub async fn save(db: &Database, conversation: &mut Conversation) -> Result<(), String> {
+    conversation.updated_at = chrono::Utc::now().to_rfc3339();
+    db.update(CONVERSATIONS, &conversation.id, conversation.clone())
+        .await
+}
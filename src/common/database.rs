@@ -1,4 +1,4 @@
-use firestore::FirestoreDb;
+use firestore::{FirestoreConsistencySelector, FirestoreDb};
 use serde::Serialize;
 use tokio_stream::StreamExt;
 
@@ -94,6 +94,40 @@ impl Database {
         }
     }
 
+    // 指定したフィールドが値と一致するドキュメントを取得する
+    pub async fn read_by<T>(
+        &self,
+        collection: &str,
+        field: &str,
+        value: &str,
+    ) -> Result<Vec<T>, String>
+    where
+        T: serde::de::DeserializeOwned + Send + Sync,
+    {
+        match self
+            .client
+            .fluent()
+            .select()
+            .from(collection)
+            .filter(|q| q.for_all([q.field(field).eq(value.to_string())]))
+            .obj::<T>()
+            .stream_query_with_errors()
+            .await
+        {
+            Ok(mut data) => {
+                let mut result = Vec::new();
+                while let Some(item) = data.next().await {
+                    match item {
+                        Ok(item) => result.push(item),
+                        Err(e) => return Err(format!("Failed to read documents: {}", e)),
+                    }
+                }
+                Ok(result)
+            }
+            Err(e) => Err(format!("Failed to read documents: {}", e)),
+        }
+    }
+
     pub async fn update<T>(&self, collection: &str, id: &str, data: T) -> Result<(), String>
     where
         T: serde::Serialize + Send + Sync + for<'de> serde::Deserialize<'de> + Serialize,
@@ -113,6 +147,65 @@ impl Database {
         }
     }
 
+    // トランザクション内で現在の値を読み、`f` が返した値で更新する
+    // 読み取りから書き込みまでの間に他から更新された場合はコミットに失敗する
+    // ドキュメントがない場合と `f` が None を返した場合は更新せず None を返す
+    pub async fn update_with<T, F>(
+        &self,
+        collection: &str,
+        id: &str,
+        f: F,
+    ) -> Result<Option<T>, String>
+    where
+        T: serde::Serialize + serde::de::DeserializeOwned + Send + Sync,
+        F: FnOnce(T) -> Option<T>,
+    {
+        let mut transaction = match self.client.begin_transaction().await {
+            Ok(transaction) => transaction,
+            Err(e) => return Err(format!("Failed to begin transaction: {}", e)),
+        };
+        let reader =
+            self.client
+                .clone_with_consistency_selector(FirestoreConsistencySelector::Transaction(
+                    transaction.transaction_id().clone(),
+                ));
+        let current = match reader
+            .fluent()
+            .select()
+            .by_id_in(collection)
+            .obj::<T>()
+            .one(id)
+            .await
+        {
+            Ok(current) => current,
+            Err(e) => {
+                let _ = transaction.rollback().await;
+                return Err(format!("Failed to read document: {}", e));
+            }
+        };
+        let Some(data) = current.and_then(f) else {
+            let _ = transaction.rollback().await;
+            return Ok(None);
+        };
+
+        if let Err(e) = self
+            .client
+            .fluent()
+            .update()
+            .in_col(collection)
+            .document_id(id)
+            .object(&data)
+            .add_to_transaction(&mut transaction)
+        {
+            let _ = transaction.rollback().await;
+            return Err(format!("Failed to update document: {}", e));
+        }
+        match transaction.commit().await {
+            Ok(_) => Ok(Some(data)),
+            Err(e) => Err(format!("Failed to update document: {}", e)),
+        }
+    }
+
     pub async fn delete(&self, collection: &str, id: &str) -> Result<(), String> {
         match self
             .client
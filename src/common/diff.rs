@@ -0,0 +1,173 @@
+use serde::{Deserialize, Serialize};
+use similar::{Algorithm, DiffTag};
+use unicode_script::Script;
+
+use crate::common::render::escape;
+
+// 元の文章と修正後の文章の差分
+// 日本語は単語を空白で区切らないため、文字種 (漢字・ひらがな・カタカナ・英数字) の連続を1語として比較する
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffOp {
+    Equal,
+    Insert,
+    Delete,
+}
+
+/// 差分の区間
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiffSpan {
+    pub op: DiffOp,
+    pub text: String,
+}
+
+/// 文字種
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Han,
+    Hiragana,
+    Katakana,
+    Word,
+    Space,
+    // 改行・記号は1文字ずつ比較する
+    Other,
+}
+
+impl Kind {
+    fn of(c: char) -> Self {
+        if c == '\n' {
+            return Kind::Other;
+        }
+        if c.is_whitespace() {
+            return Kind::Space;
+        }
+        match Script::from(c) {
+            Script::Han => Kind::Han,
+            Script::Hiragana => Kind::Hiragana,
+            Script::Katakana => Kind::Katakana,
+            // 長音符は前後のカタカナとつなげる
+            _ if c == 'ー' => Kind::Katakana,
+            _ if c.is_alphanumeric() || c == '_' => Kind::Word,
+            _ => Kind::Other,
+        }
+    }
+}
+
+/// 文字種の連続で区切る
+fn tokenize(text: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let mut prev: Option<Kind> = None;
+    for (i, c) in text.char_indices() {
+        let kind = Kind::of(c);
+        if prev.is_some_and(|p| p != kind || kind == Kind::Other) {
+            tokens.push(&text[start..i]);
+            start = i;
+        }
+        prev = Some(kind);
+    }
+    if start < text.len() {
+        tokens.push(&text[start..]);
+    }
+    tokens
+}
+
+/// 単語単位の差分
+/// 同じ種類の区間が続く場合は1つにまとめる (置換は削除、挿入の順に並べる)
+pub fn diff(old: &str, new: &str) -> Vec<DiffSpan> {
+    let old_tokens = tokenize(old);
+    let new_tokens = tokenize(new);
+
+    let mut spans: Vec<DiffSpan> = Vec::new();
+    let mut push = |op: DiffOp, tokens: &[&str]| {
+        let text = tokens.concat();
+        if text.is_empty() {
+            return;
+        }
+        match spans.last_mut() {
+            Some(last) if last.op == op => last.text.push_str(&text),
+            _ => spans.push(DiffSpan { op, text }),
+        }
+    };
+    for op in similar::capture_diff_slices(Algorithm::Patience, &old_tokens, &new_tokens) {
+        let (tag, old_range, new_range) = op.as_tag_tuple();
+        match tag {
+            DiffTag::Equal => push(DiffOp::Equal, &old_tokens[old_range]),
+            DiffTag::Delete => push(DiffOp::Delete, &old_tokens[old_range]),
+            DiffTag::Insert => push(DiffOp::Insert, &new_tokens[new_range]),
+            DiffTag::Replace => {
+                push(DiffOp::Delete, &old_tokens[old_range]);
+                push(DiffOp::Insert, &new_tokens[new_range]);
+            }
+        }
+    }
+    spans
+}
+
+/// 差分を `<ins>` と `<del>` で表したHTML
+/// 改行はそのまま残すため、表示側で `white-space: pre-wrap` などを指定する
+pub fn to_html(spans: &[DiffSpan]) -> String {
+    let mut html = String::new();
+    for span in spans {
+        let text = escape(&span.text);
+        match span.op {
+            DiffOp::Equal => html.push_str(&text),
+            DiffOp::Insert => html.push_str(&format!("<ins>{}</ins>", text)),
+            DiffOp::Delete => html.push_str(&format!("<del>{}</del>", text)),
+        }
+    }
+    html
+}
+
+#[cfg(test)]
+// 空白のない日本語の差分とHTMLの出力を確認する
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            tokenize("資料をご確認ください。Meeting は3時から"),
+            vec![
+                "資料",
+                "をご",
+                "確認",
+                "ください",
+                "。",
+                "Meeting",
+                " ",
+                "は",
+                "3",
+                "時",
+                "から"
+            ]
+        );
+        assert_eq!(tokenize("サーバー"), vec!["サーバー"]);
+    }
+
+    #[test]
+    fn test_diff() {
+        let spans = diff("明日の会議に参加します。", "明後日の会議に出席いたします。");
+        let text = |op: DiffOp| {
+            spans
+                .iter()
+                .filter(|s| s.op == op)
+                .map(|s| s.text.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(text(DiffOp::Delete), vec!["明日", "参加します"]);
+        assert_eq!(text(DiffOp::Insert), vec!["明後日", "出席いたします"]);
+        assert_eq!(
+            spans
+                .iter()
+                .filter(|s| s.op != DiffOp::Insert)
+                .map(|s| s.text.as_str())
+                .collect::<String>(),
+            "明日の会議に参加します。"
+        );
+
+        let html = to_html(&diff("<b>A</b>", "<b>B</b>"));
+        assert_eq!(html, "&lt;b&gt;<del>A</del><ins>B</ins>&lt;/b&gt;");
+    }
+}
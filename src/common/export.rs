@@ -0,0 +1,579 @@
+use std::sync::LazyLock;
+
+use chrono::{Datelike, Days, Months, NaiveDate, NaiveTime, Utc, Weekday};
+use regex::Regex;
+use serde::Serialize;
+
+use crate::models::meeting::MeetingMinutes;
+
+// 議事録の ToDo・次回予定をカレンダー (iCalendar) や表計算 (CSV) に取り込める形式に変換する
+// 「来週金曜」などの相対的な期限は会議の日付を基準に日付へ変換する
+
+static YMD: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(\d{4})\s*[年/\-.]\s*(\d{1,2})\s*[月/\-.]\s*(\d{1,2})").unwrap());
+static MD: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(\d{1,2})\s*[月/]\s*(\d{1,2})").unwrap());
+static MONTH_END: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(\d{1,2})\s*月\s*(?:末|中)").unwrap());
+static AFTER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(\d+)\s*(日|週間|ヶ月|か月|カ月|ケ月)\s*(?:後|以内)").unwrap());
+static NEXT_MONTH_DAY: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(再来月|来月|今月)\s*の?\s*(\d{1,2})\s*日").unwrap());
+static RELATIVE_MONTH_END: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?:(再来月|来月|今月)|月)末").unwrap());
+static WEEK_END: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(再来週|来週|今週)\s*(?:末|中)").unwrap());
+static WEEK_DAY: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(再来週|来週|今週)\s*の?\s*\(?([月火水木金土日])(?:曜|\)|$)").unwrap()
+});
+static WEEKDAY: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"([月火水木金土日])曜").unwrap());
+static DAY: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(\d{1,2})\s*日").unwrap());
+static TIME: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(午前|午後)?\s*(\d{1,2})\s*(?::|時)\s*(\d{2})?").unwrap());
+
+/// 全角の数字・記号を半角にする
+fn normalize(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '０'..='９' => char::from_u32(c as u32 - '０' as u32 + '0' as u32).unwrap_or(c),
+            '／' => '/',
+            '：' => ':',
+            '－' => '-',
+            '（' => '(',
+            '）' => ')',
+            '　' => ' ',
+            _ => c,
+        })
+        .collect()
+}
+
+fn number(s: &str) -> u32 {
+    s.parse().unwrap_or_default()
+}
+
+fn weekday(c: &str) -> Option<Weekday> {
+    match c {
+        "月" => Some(Weekday::Mon),
+        "火" => Some(Weekday::Tue),
+        "水" => Some(Weekday::Wed),
+        "木" => Some(Weekday::Thu),
+        "金" => Some(Weekday::Fri),
+        "土" => Some(Weekday::Sat),
+        "日" => Some(Weekday::Sun),
+        _ => None,
+    }
+}
+
+fn week_offset(s: &str) -> u64 {
+    match s {
+        "再来週" => 2,
+        "来週" => 1,
+        _ => 0,
+    }
+}
+
+fn month_offset(s: &str) -> u32 {
+    match s {
+        "再来月" => 2,
+        "来月" => 1,
+        _ => 0,
+    }
+}
+
+/// 基準日から offset 週後の週 (月曜始まり) の指定曜日
+fn day_of_week(base: NaiveDate, offset: u64, day: Weekday) -> Option<NaiveDate> {
+    let monday = base.checked_sub_days(Days::new(base.weekday().num_days_from_monday() as u64))?;
+    monday.checked_add_days(Days::new(offset * 7 + day.num_days_from_monday() as u64))
+}
+
+/// 指定した月の末日
+fn last_day_of_month(year: i32, month: u32) -> Option<NaiveDate> {
+    NaiveDate::from_ymd_opt(year, month, 1)?
+        .checked_add_months(Months::new(1))?
+        .pred_opt()
+}
+
+/// 年の省略された日付は、基準日より前であれば翌年とみなす
+fn with_year(base: NaiveDate, month: u32, day: u32) -> Option<NaiveDate> {
+    let date = NaiveDate::from_ymd_opt(base.year(), month, day)?;
+    if date < base {
+        NaiveDate::from_ymd_opt(base.year() + 1, month, day)
+    } else {
+        Some(date)
+    }
+}
+
+/// 期限などの日付表現を基準日 (会議の日付) から解釈する
+///
+/// - 絶対日付: `2025年4月10日`, `2025/4/10`, `2025-04-10`, `4月10日`, `4/10`, `4月末`
+/// - 相対日付: `今日`, `明日`, `明後日`, `3日後`, `2週間以内`, `1ヶ月後`
+/// - 曜日: `来週金曜`, `今週水曜日`, `再来週(月)`, `金曜まで` (基準日より後の直近の曜日)
+/// - 週・月の区切り: `来週中`, `今週末` (その週の金曜), `月末`, `来月末`, `来月10日`, `15日`
+///
+/// 解釈できない場合は None
+pub fn resolve_date(text: &str, base: NaiveDate) -> Option<NaiveDate> {
+    let text = normalize(text);
+    let text = text.trim();
+
+    if let Some(c) = YMD.captures(text) {
+        let year = c[1].parse::<i32>().ok()?;
+        return NaiveDate::from_ymd_opt(year, number(&c[2]), number(&c[3]));
+    }
+    if let Some(c) = MD.captures(text) {
+        return with_year(base, number(&c[1]), number(&c[2]));
+    }
+    if let Some(c) = MONTH_END.captures(text) {
+        let month = number(&c[1]);
+        let date = last_day_of_month(base.year(), month)?;
+        if date < base {
+            return last_day_of_month(base.year() + 1, month);
+        }
+        return Some(date);
+    }
+
+    if text.contains("今日") || text.contains("本日") || text.contains("当日") {
+        return Some(base);
+    }
+    if text.contains("明後日") {
+        return base.checked_add_days(Days::new(2));
+    }
+    if text.contains("明日") {
+        return base.checked_add_days(Days::new(1));
+    }
+
+    if let Some(c) = AFTER.captures(text) {
+        let n = number(&c[1]);
+        return match &c[2] {
+            "日" => base.checked_add_days(Days::new(n as u64)),
+            "週間" => base.checked_add_days(Days::new(n as u64 * 7)),
+            _ => base.checked_add_months(Months::new(n)),
+        };
+    }
+    if let Some(c) = NEXT_MONTH_DAY.captures(text) {
+        let month = base.checked_add_months(Months::new(month_offset(&c[1])))?;
+        return NaiveDate::from_ymd_opt(month.year(), month.month(), number(&c[2]));
+    }
+    if let Some(c) = WEEK_END.captures(text) {
+        return day_of_week(base, week_offset(&c[1]), Weekday::Fri);
+    }
+    if let Some(c) = RELATIVE_MONTH_END.captures(text) {
+        let offset = c.get(1).map(|m| month_offset(m.as_str())).unwrap_or(0);
+        let month = base.with_day(1)?.checked_add_months(Months::new(offset))?;
+        return last_day_of_month(month.year(), month.month());
+    }
+    if let Some(c) = WEEK_DAY.captures(text) {
+        return day_of_week(base, week_offset(&c[1]), weekday(&c[2])?);
+    }
+    if let Some(c) = WEEKDAY.captures(text) {
+        let day = weekday(&c[1])?;
+        let days = (7 + day.num_days_from_monday() as i64
+            - base.weekday().num_days_from_monday() as i64
+            - 1)
+            % 7
+            + 1;
+        return base.checked_add_days(Days::new(days as u64));
+    }
+    if let Some(c) = DAY.captures(text) {
+        let day = number(&c[1]);
+        let date = NaiveDate::from_ymd_opt(base.year(), base.month(), day);
+        return match date {
+            Some(date) if date >= base => Some(date),
+            _ => {
+                let next = base.with_day(1)?.checked_add_months(Months::new(1))?;
+                NaiveDate::from_ymd_opt(next.year(), next.month(), day)
+            }
+        };
+    }
+
+    None
+}
+
+/// 日時の表現から開始・終了時刻を読み取る
+/// `10:00 - 11:00`, `午後3時`, `15時30分` など
+pub fn resolve_times(text: &str) -> (Option<NaiveTime>, Option<NaiveTime>) {
+    let text = normalize(text);
+    let mut times = TIME.captures_iter(&text).filter_map(|c| {
+        let mut hour = number(&c[2]);
+        if c.get(1).is_some_and(|m| m.as_str() == "午後") && hour < 12 {
+            hour += 12;
+        }
+        let minute = c.get(3).map(|m| number(m.as_str())).unwrap_or(0);
+        NaiveTime::from_hms_opt(hour, minute, 0)
+    });
+    (times.next(), times.next())
+}
+
+/// 期限として扱わない記載
+fn is_blank(text: &str) -> bool {
+    matches!(text.trim(), "" | "不明" | "未定" | "なし" | "-")
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemKind {
+    Todo,
+    NextMeeting,
+}
+
+/// 書き出す1件分の予定
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportItem {
+    pub kind: ItemKind,
+    pub assignee: Option<String>,
+    pub content: String,
+    // 議事録に書かれた期限・日時の原文
+    pub due_text: Option<String>,
+    pub date: Option<NaiveDate>,
+    pub start: Option<NaiveTime>,
+    pub end: Option<NaiveTime>,
+    // 期限の記載はあるが日付として解釈できなかった
+    pub needs_review: bool,
+}
+
+impl ExportItem {
+    fn new(
+        kind: ItemKind,
+        assignee: Option<String>,
+        content: String,
+        due_text: Option<String>,
+        base: NaiveDate,
+    ) -> Self {
+        let due_text = due_text.filter(|text| !is_blank(text));
+        let date = due_text
+            .as_deref()
+            .and_then(|text| resolve_date(text, base));
+        let (start, end) = match (&kind, &due_text) {
+            (ItemKind::NextMeeting, Some(text)) => resolve_times(text),
+            _ => (None, None),
+        };
+        ExportItem {
+            kind,
+            assignee: assignee.filter(|a| !is_blank(a)),
+            content,
+            needs_review: due_text.is_some() && date.is_none(),
+            due_text,
+            date,
+            start,
+            end,
+        }
+    }
+}
+
+/// 議事録から書き出す予定の一覧
+#[derive(Debug, Clone, Serialize)]
+pub struct Export {
+    pub title: String,
+    pub meeting_date: NaiveDate,
+    pub items: Vec<ExportItem>,
+}
+
+impl Export {
+    /// `base` は会議の日付。相対的な期限の基準にする
+    pub fn from_minutes(minutes: &MeetingMinutes, base: NaiveDate) -> Self {
+        let mut items = minutes
+            .todos
+            .iter()
+            .map(|todo| {
+                ExportItem::new(
+                    ItemKind::Todo,
+                    todo.assignee.clone(),
+                    todo.task.clone(),
+                    todo.due.clone(),
+                    base,
+                )
+            })
+            .collect::<Vec<ExportItem>>();
+
+        if let Some(next) = &minutes.next_meeting {
+            let agenda = next.agenda.clone().filter(|a| !is_blank(a));
+            items.push(ExportItem::new(
+                ItemKind::NextMeeting,
+                None,
+                agenda.unwrap_or_else(|| "次回会議".to_string()),
+                next.date.clone(),
+                base,
+            ));
+        }
+
+        Export {
+            title: minutes.title.clone(),
+            meeting_date: base,
+            items,
+        }
+    }
+
+    /// 日付として解釈できなかった件数
+    pub fn unresolved(&self) -> usize {
+        self.items.iter().filter(|item| item.needs_review).count()
+    }
+
+    /// iCalendar (RFC 5545)
+    /// 多くのカレンダーアプリが VTODO を取り込まないため、ToDo も終日の VEVENT として書き出す
+    /// 日付が確定しない項目は含めない (CSV / JSON では要確認として残る)
+    pub fn to_ics(&self) -> String {
+        let stamp = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+        let mut lines = vec![
+            "BEGIN:VCALENDAR".to_string(),
+            "VERSION:2.0".to_string(),
+            "PRODID:-//boilarplate_api_rs//meeting export//JA".to_string(),
+            "CALSCALE:GREGORIAN".to_string(),
+            "METHOD:PUBLISH".to_string(),
+            format!("X-WR-CALNAME:{}", escape_text(&self.title)),
+            // 日本時間のみ扱う (夏時間なし)
+            "BEGIN:VTIMEZONE".to_string(),
+            "TZID:Asia/Tokyo".to_string(),
+            "BEGIN:STANDARD".to_string(),
+            "DTSTART:19700101T000000".to_string(),
+            "TZOFFSETFROM:+0900".to_string(),
+            "TZOFFSETTO:+0900".to_string(),
+            "TZNAME:JST".to_string(),
+            "END:STANDARD".to_string(),
+            "END:VTIMEZONE".to_string(),
+        ];
+
+        for item in &self.items {
+            let Some(date) = item.date else {
+                continue;
+            };
+
+            let (summary, mut description) = match item.kind {
+                ItemKind::Todo => (
+                    format!("[ToDo] {}", item.content),
+                    vec![format!("会議: {}", self.title)],
+                ),
+                ItemKind::NextMeeting => (
+                    format!("次回: {}", self.title),
+                    vec![format!("議題: {}", item.content)],
+                ),
+            };
+            if let Some(assignee) = &item.assignee {
+                description.push(format!("担当者: {}", assignee));
+            }
+            if let Some(text) = &item.due_text {
+                description.push(format!("期限(原文): {}", text));
+            }
+
+            lines.push("BEGIN:VEVENT".to_string());
+            lines.push(format!("UID:{}@boilarplate_api_rs", uuid::Uuid::new_v4()));
+            lines.push(format!("DTSTAMP:{}", stamp));
+            match item.start {
+                Some(start) => {
+                    // 終了時刻がなければ1時間とする
+                    let start = date.and_time(start);
+                    let end = item
+                        .end
+                        .map(|end| date.and_time(end))
+                        .filter(|end| *end > start)
+                        .unwrap_or(start + chrono::Duration::hours(1));
+                    lines.push(format!(
+                        "DTSTART;TZID=Asia/Tokyo:{}",
+                        start.format("%Y%m%dT%H%M%S")
+                    ));
+                    lines.push(format!(
+                        "DTEND;TZID=Asia/Tokyo:{}",
+                        end.format("%Y%m%dT%H%M%S")
+                    ));
+                }
+                None => {
+                    lines.push(format!("DTSTART;VALUE=DATE:{}", date.format("%Y%m%d")));
+                    lines.push(format!(
+                        "DTEND;VALUE=DATE:{}",
+                        date.succ_opt().unwrap_or(date).format("%Y%m%d")
+                    ));
+                }
+            }
+            lines.push(format!("SUMMARY:{}", escape_text(&summary)));
+            lines.push(format!(
+                "DESCRIPTION:{}",
+                escape_text(&description.join("\n"))
+            ));
+            lines.push("TRANSP:TRANSPARENT".to_string());
+            lines.push("END:VEVENT".to_string());
+        }
+        lines.push("END:VCALENDAR".to_string());
+
+        lines
+            .iter()
+            .map(|line| fold_line(line))
+            .collect::<Vec<String>>()
+            .join("")
+    }
+
+    /// CSV (RFC 4180)
+    /// Excel で文字化けしないよう BOM 付きの UTF-8 で書き出す
+    pub fn to_csv(&self) -> String {
+        let mut rows = vec![
+            ["種別", "担当者", "内容", "期限(原文)", "期限", "要確認"]
+                .iter()
+                .map(|s| s.to_string())
+                .collect::<Vec<String>>(),
+        ];
+        for item in &self.items {
+            let kind = match item.kind {
+                ItemKind::Todo => "ToDo",
+                ItemKind::NextMeeting => "次回予定",
+            };
+            let date = match (item.date, item.start) {
+                (Some(date), Some(start)) => {
+                    format!("{} {}", date.format("%Y-%m-%d"), start.format("%H:%M"))
+                }
+                (Some(date), None) => date.format("%Y-%m-%d").to_string(),
+                _ => String::new(),
+            };
+            rows.push(vec![
+                kind.to_string(),
+                item.assignee.clone().unwrap_or_default(),
+                item.content.clone(),
+                item.due_text.clone().unwrap_or_default(),
+                date,
+                if item.needs_review { "要確認" } else { "" }.to_string(),
+            ]);
+        }
+
+        let body = rows
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|field| escape_csv(field))
+                    .collect::<Vec<String>>()
+                    .join(",")
+            })
+            .collect::<Vec<String>>()
+            .join("\r\n");
+        format!("\u{feff}{}\r\n", body)
+    }
+}
+
+/// iCalendar の TEXT 値のエスケープ
+fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+/// 75 オクテットを超える行を折り返す
+/// マルチバイト文字の途中では折り返さない
+fn fold_line(line: &str) -> String {
+    let mut folded = String::new();
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            width = 1;
+        }
+        folded.push(c);
+        width += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+/// 区切り文字・引用符・改行を含むフィールドは引用符で囲む
+fn escape_csv(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+// 相対的な期限の解釈と書き出し形式を確認する
+mod tests {
+    use super::*;
+    use crate::models::meeting::{NextMeeting, Todo};
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn test_resolve_date() {
+        // 2025-04-02 は水曜日
+        let base = date(2025, 4, 2);
+        let cases = [
+            ("2025年4月10日", Some(date(2025, 4, 10))),
+            ("２０２５／４／１０", Some(date(2025, 4, 10))),
+            ("4/10まで", Some(date(2025, 4, 10))),
+            ("1月15日", Some(date(2026, 1, 15))),
+            ("4月末", Some(date(2025, 4, 30))),
+            ("明日", Some(date(2025, 4, 3))),
+            ("本日中", Some(date(2025, 4, 2))),
+            ("3日後", Some(date(2025, 4, 5))),
+            ("2週間以内", Some(date(2025, 4, 16))),
+            ("来週金曜", Some(date(2025, 4, 11))),
+            ("今週金曜日まで", Some(date(2025, 4, 4))),
+            ("再来週(月)", Some(date(2025, 4, 14))),
+            ("水曜", Some(date(2025, 4, 9))),
+            ("来週中", Some(date(2025, 4, 11))),
+            ("月末", Some(date(2025, 4, 30))),
+            ("週末", None),
+            ("来月末", Some(date(2025, 5, 31))),
+            ("来月10日", Some(date(2025, 5, 10))),
+            ("1日", Some(date(2025, 5, 1))),
+            ("近日中", None),
+            ("次のリリースまで", None),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(resolve_date(text, base), expected, "{}", text);
+        }
+
+        assert_eq!(
+            resolve_times("2025年4月9日 午後3時 - 16:30"),
+            (
+                NaiveTime::from_hms_opt(15, 0, 0),
+                NaiveTime::from_hms_opt(16, 30, 0)
+            )
+        );
+    }
+
+    #[test]
+    fn test_export() {
+        let minutes = MeetingMinutes {
+            title: "定例会議".to_string(),
+            todos: vec![
+                Todo {
+                    assignee: Some("佐藤 花子".to_string()),
+                    task: "見積もり作成".to_string(),
+                    due: Some("来週金曜".to_string()),
+                },
+                Todo {
+                    assignee: None,
+                    task: "議事録の共有, 確認".to_string(),
+                    due: Some("近日中".to_string()),
+                },
+                Todo {
+                    assignee: Some("不明".to_string()),
+                    task: "資料の整理".to_string(),
+                    due: Some("未定".to_string()),
+                },
+            ],
+            next_meeting: Some(NextMeeting {
+                date: Some("4月9日 10:00 - 11:00".to_string()),
+                agenda: Some("進捗確認".to_string()),
+            }),
+            ..Default::default()
+        };
+        let export = Export::from_minutes(&minutes, date(2025, 4, 2));
+
+        // 解釈できない期限のみ要確認とする (未定は期限なし)
+        assert_eq!(export.unresolved(), 1);
+        assert!(export.items[1].needs_review);
+        assert!(!export.items[2].needs_review);
+
+        let ics = export.to_ics();
+        assert!(ics.contains("DTSTART;VALUE=DATE:20250411\r\n"));
+        assert!(ics.contains("DTSTART;TZID=Asia/Tokyo:20250409T100000\r\n"));
+        assert!(ics.contains("DTEND;TZID=Asia/Tokyo:20250409T110000\r\n"));
+        assert_eq!(ics.matches("BEGIN:VEVENT").count(), 2);
+        assert!(ics.lines().all(|line| line.len() <= 75));
+
+        let csv = export.to_csv();
+        assert!(csv.starts_with("\u{feff}種別,担当者,内容,期限(原文),期限,要確認\r\n"));
+        assert!(csv.contains("ToDo,佐藤 花子,見積もり作成,来週金曜,2025-04-11,\r\n"));
+        assert!(csv.contains("ToDo,,\"議事録の共有, 確認\",近日中,,要確認\r\n"));
+        assert!(csv.contains("次回予定,,進捗確認,4月9日 10:00 - 11:00,2025-04-09 10:00,\r\n"));
+    }
+}
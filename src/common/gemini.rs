@@ -1,158 +1,449 @@
-use log::{error, info};
-use serde_json::{Value, json};
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex},
+    time::{Duration, Instant},
+};
 
-// request to Gemini API
-// parse response
-pub async fn request(request_content: &str) -> Result<Value, String> {
-    let mut model = std::env::var("GEMINI_MODEL").unwrap_or_default();
+use log::{error, info, warn};
+use tokio::sync::mpsc;
 
-    if model.is_empty() {
-        let models = std::env::var("GEMINI_MODELS").unwrap_or_default();
-        model = models.split(',').collect::<Vec<&str>>()[0].to_string();
-    }
+use crate::{
+    common::{
+        http::Http,
+        provider::{AiError, AiRequest, AiResponse, Provider, Usage, call, env_or},
+    },
+    models::gemini::{
+        Candidate, Content, GenerateContentRequest, GenerateContentResponse, GenerationConfig,
+        SafetyRating,
+    },
+};
+
+/// モデルごとの失敗状況
+/// 連続して失敗したモデルは一定時間スキップする
+#[derive(Debug, Default)]
+struct ModelHealth {
+    // 連続失敗回数
+    failures: u32,
+    // この時刻まではスキップする
+    cooldown_until: Option<Instant>,
+}
+
+static HEALTH: LazyLock<Mutex<HashMap<String, ModelHealth>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+// 連続失敗回数がこの値に達したらクールダウンに入る
+fn failure_threshold() -> u32 {
+    env_or("GEMINI_FAILURE_THRESHOLD", "3").parse().unwrap_or(3)
+}
+
+// クールダウンの秒数
+fn cooldown() -> Duration {
+    Duration::from_secs(env_or("GEMINI_COOLDOWN_SECS", "300").parse().unwrap_or(300))
+}
+
+fn record_success(model: &str) {
+    let mut health = HEALTH.lock().unwrap();
+    health.remove(model);
+}
 
-    let mut token = std::env::var("GEMINI_API_TOKEN").unwrap_or_default();
-    if token.is_empty() {
-        token = std::env::var("GOOGLE_GEMINI_API_KEY").unwrap_or_default();
+fn record_failure(model: &str) {
+    let mut health = HEALTH.lock().unwrap();
+    let entry = health.entry(model.to_string()).or_default();
+    entry.failures += 1;
+    if entry.failures >= failure_threshold() {
+        warn!(
+            "model {} failed {} times, skip for {:?}",
+            model,
+            entry.failures,
+            cooldown()
+        );
+        entry.failures = 0;
+        entry.cooldown_until = Some(Instant::now() + cooldown());
     }
+}
+
+fn is_cooling_down(model: &str) -> bool {
+    let health = HEALTH.lock().unwrap();
+    health
+        .get(model)
+        .and_then(|h| h.cooldown_until)
+        .is_some_and(|until| Instant::now() < until)
+}
 
-    if model.is_empty() || token.is_empty() {
-        return Err("GEMINI_MODEL or GEMINI_API_TOKEN is empty".to_string());
+/// 失敗の種類
+/// 次のモデルに切り替えることで回復しうるかどうかを区別する
+enum Failure {
+    // クォータ超過、5xx、候補なし
+    Fallback(AiError),
+    // リクエスト自体の誤りなど、モデルを変えても回復しないもの
+    Fatal(AiError),
+}
+
+/// Gemini API
+/// generateContent エンドポイントへリクエストする
+#[derive(Debug, Clone)]
+pub struct Gemini {
+    http: Http,
+    base_url: String,
+    // 優先順のモデル一覧
+    models: Vec<String>,
+    token: String,
+}
+
+impl Gemini {
+    pub fn new(http: Http, base_url: String, models: Vec<String>, token: String) -> Self {
+        Self {
+            http,
+            base_url,
+            models,
+            token,
+        }
     }
 
-    let url = format!(
-        "https://generativelanguage.googleapis.com/v1beta/models/{}:generateContent?key={}",
-        model.clone(),
-        token.clone()
-    );
+    // 環境変数から設定を読み込む
+    // GEMINI_MODEL があれば最優先とし、続けて GEMINI_MODELS の順に試す
+    pub fn from_env(http: Http) -> Result<Self, AiError> {
+        let mut models = Vec::new();
+        let model = std::env::var("GEMINI_MODEL").unwrap_or_default();
+        if !model.is_empty() {
+            models.push(model);
+        }
+        for model in std::env::var("GEMINI_MODELS")
+            .unwrap_or_default()
+            .split(',')
+        {
+            let model = model.trim();
+            if !model.is_empty() && !models.iter().any(|m| m == model) {
+                models.push(model.to_string());
+            }
+        }
 
-    match inner_request(&url, &request_content).await {
-        Ok(s) => {
-            info!("{:?}", s.clone());
-            // let serded_value: Todo = match serde_json::from_str(s.clone().as_str()) {
-            //     Ok(v) => v,
-            //     Err(e) => {
-            //         error!("parsed error: {:?}, {:?}", e, s);
-            //         return Err(e.to_string());
-            //     }
-            // };
-            // info!("serded: {:?}", serded_value);
+        let mut token = std::env::var("GEMINI_API_TOKEN").unwrap_or_default();
+        if token.is_empty() {
+            token = std::env::var("GOOGLE_GEMINI_API_KEY").unwrap_or_default();
+        }
 
-            Ok(json!({
-                "model": model,
-                "result": s.clone(),
-            }))
+        if models.is_empty() || token.is_empty() {
+            return Err(AiError::Config(
+                "GEMINI_MODEL or GEMINI_API_TOKEN is empty".to_string(),
+            ));
         }
-        Err(e) => {
-            error!("error: {:?}", e);
-            Err(e.to_string())
+
+        let base_url = env_or(
+            "GEMINI_BASE_URL",
+            "https://generativelanguage.googleapis.com",
+        );
+
+        Ok(Self::new(http, base_url, models, token))
+    }
+
+    // クールダウン中のモデルを除いた試行順
+    // 全てクールダウン中の場合は、全モデルを順に試す
+    fn available_models(&self) -> Vec<String> {
+        let models = self
+            .models
+            .iter()
+            .filter(|m| !is_cooling_down(m))
+            .cloned()
+            .collect::<Vec<String>>();
+        if models.is_empty() {
+            self.models.clone()
+        } else {
+            models
         }
     }
 }
 
-async fn inner_request(url: &str, str: &str) -> Result<String, String> {
-    let client = reqwest::Client::new();
-    let mut headers = reqwest::header::HeaderMap::new();
-    headers.insert("Content-Type", "application/json".parse().unwrap());
+impl Gemini {
+    /// streamGenerateContent へリクエストし、部分テキストを受信するたびに `tx` へ送信する
+    /// 完了後は連結した全文を返す
+    pub async fn request_stream(
+        &self,
+        request: &AiRequest,
+        tx: mpsc::Sender<String>,
+    ) -> Result<AiResponse, AiError> {
+        // ストリーミング中はモデルを切り替えられないため、利用可能な先頭のモデルを使う
+        let model = self.available_models()[0].clone();
+        let url = format!(
+            "{}/v1beta/models/{}:streamGenerateContent?alt=sse&key={}",
+            self.base_url, model, self.token
+        );
 
-    let body = json!({
-        "contents": [
-            {
-                "parts": [
-                    {
-                        "text": str
-                    }
-                ]
+        let builder = self.http.client.post(url).json(&request_body(request));
+        let mut res = self.http.send(builder).await?;
+        if !res.status().is_success() {
+            let status = res.status();
+            if status.as_u16() == 429 || status.is_server_error() {
+                record_failure(&model);
             }
-        ],
-        "generationConfig": {
-            "temperature": 0.1,
-            "maxOutputTokens": 8192,
-            "topK": 1,
-            "topP": 0.1,
+            let err = Http::upstream_error(res).await;
+            error!("stream error: {}", err);
+            return Err(err);
         }
-    });
 
-    let res = match client.post(url).headers(headers).json(&body).send().await {
-        Ok(v) => v,
-        Err(e) => return Err(e.to_string()),
-    };
+        // SSE は `data: {json}` 行の連続で届く
+        // チャンクの境界は行の境界と一致しないため、改行までバッファする
+        let mut buffer = String::new();
+        let mut result = String::new();
+        // 終了理由・トークン使用量は最後のチャンクに含まれる
+        let mut last = GenerateContentResponse::default();
+        loop {
+            let chunk = match res.chunk().await {
+                Ok(Some(chunk)) => chunk,
+                Ok(None) => break,
+                Err(e) => return Err(AiError::from(e)),
+            };
+            buffer.push_str(&String::from_utf8_lossy(&chunk));
 
-    // 1. JSON文字列を `serde_json::Value` にパース
-    match res.json::<Value>().await {
-        Ok(value) => {
-            // 2. `candidates` 配列を取得 (Option<&Value> として)
-            //    `value["candidates"]` は存在しないキーだと panic する可能性があるため、
-            //    `get()` を使うのが安全。
-            let candidates_value = value.get("candidates");
-
-            // 3. `candidates` が配列であることを確認し、最初の要素を取得
-            //    `and_then` を使うとネストを避けられる
-            let first_candidate_value = candidates_value
-                .and_then(|candidates| candidates.as_array()) // candidates が配列なら Some(&Vec<Value>)
-                .and_then(|arr| arr.first()); // 配列が空でなければ Some(&Value)
-
-            // 4. 最初の候補 (candidate) がオブジェクトであることを確認し、`content` を取得
-            let content_value = first_candidate_value
-                .and_then(|candidate| candidate.as_object()) // candidate がオブジェクトなら Some(&Map<String, Value>)
-                .and_then(|obj| obj.get("content")); // オブジェクトに "content" キーがあれば Some(&Value)
-
-            // 5. `content` の値が見つかったかどうかで分岐
-            match content_value {
-                Some(content) => {
-                    // --- さらに content の中の text を取得する例 ---
-                    // content["parts"][0]["text"] のようなアクセスを安全に行う
-
-                    let text = content
-                        .get("parts") // Some(&Value) (parts 配列) or None
-                        .and_then(|parts| parts.as_array()) // Some(&Vec<Value>) or None
-                        .and_then(|arr| arr.first()) // Some(&Value) (最初の part オブジェクト) or None
-                        .and_then(|part| part.as_object()) // Some(&Map<String, Value>) or None
-                        .and_then(|obj| obj.get("text")) // Some(&Value) (text 文字列) or None
-                        .and_then(|text_val| text_val.as_str()); // Some(&str) or None
-
-                    match text {
-                        Some(_) => (),
-                        None => println!(
-                            "`content.parts[0].text` が見つからないか、文字列ではありませんでした。"
-                        ),
-                    }
+            while let Some(pos) = buffer.find('\n') {
+                let line = buffer[..pos].trim_end_matches('\r').to_string();
+                buffer.drain(..=pos);
 
-                    // 全ての part の text を連結する場合
-                    let all_texts =
-                        content
-                            .get("parts")
-                            .and_then(|parts| parts.as_array())
-                            .map(|arr| {
-                                // Option::map を使う
-                                arr.iter()
-                                    .filter_map(|part| part.get("text")) // 各 part から "text" を取得 (Option<&Value>)
-                                    .filter_map(|text_val| text_val.as_str()) // 文字列なら &str に変換
-                                    .collect::<Vec<&str>>() // &str の Vec を作成
-                            }); // 結果は Option<Vec<&str>>
-
-                    if let Some(texts) = all_texts {
-                        Ok(texts.join(""))
-                    } else {
-                        println!("`content.parts` が配列でないか、見つかりませんでした。");
-                        Err("`content.parts` が配列でないか、見つかりませんでした。".to_string())
+                let Some(data) = line.strip_prefix("data:") else {
+                    continue;
+                };
+                let value = match serde_json::from_str::<GenerateContentResponse>(data.trim()) {
+                    Ok(v) => v,
+                    Err(e) => {
+                        error!("JSONのパースに失敗しました: {}, {}", e, data);
+                        continue;
                     }
+                };
+                let text = value.text();
+                merge_chunk(&mut last, value);
+                if text.is_empty() {
+                    continue;
+                }
+                result.push_str(&text);
+                // 受信側が切断していても、全文の取得は続ける
+                let _ = tx.send(text).await;
+            }
+        }
+
+        check_finish(&last)?;
+        if result.is_empty() {
+            record_failure(&model);
+            return Err(AiError::Upstream(
+                "streamGenerateContent からテキストを受信できませんでした。".to_string(),
+            ));
+        }
+        record_success(&model);
 
-                    // 必要であれば content オブジェクトを所有権付きでコピー
-                    // let owned_content: Value = content.clone();
+        Ok(to_response(model, result, &last))
+    }
+}
+
+// ストリーミングのチャンクから、終了理由・安全性評価・トークン使用量を引き継ぐ
+fn merge_chunk(last: &mut GenerateContentResponse, chunk: GenerateContentResponse) {
+    if chunk.prompt_feedback.is_some() {
+        last.prompt_feedback = chunk.prompt_feedback;
+    }
+    if chunk.usage_metadata.is_some() {
+        last.usage_metadata = chunk.usage_metadata;
+    }
+    if let Some(candidate) = chunk.candidates.into_iter().next() {
+        let merged = match last.candidates.pop() {
+            Some(mut merged) => {
+                if candidate.finish_reason.is_some() {
+                    merged.finish_reason = candidate.finish_reason;
                 }
-                None => {
-                    let msg = format!(
-                        "`candidates` 配列の最初の要素、またはその中の `content` フィールドが見つかりませんでした。  {}",
-                        value
-                    );
-                    Err(msg)
+                if !candidate.safety_ratings.is_empty() {
+                    merged.safety_ratings = candidate.safety_ratings;
                 }
+                merged
             }
+            None => Candidate {
+                content: None,
+                ..candidate
+            },
+        };
+        last.candidates = vec![merged];
+    }
+}
+
+// 安全性によるブロック・出力上限による打ち切りを個別のエラーとして扱う
+fn check_finish(response: &GenerateContentResponse) -> Result<(), AiError> {
+    if let Some(feedback) = response
+        .prompt_feedback
+        .as_ref()
+        .filter(|f| f.block_reason.is_some())
+    {
+        return Err(AiError::Safety(format!(
+            "prompt was blocked: {}, {}",
+            feedback.block_reason.clone().unwrap_or_default(),
+            blocked_categories(&feedback.safety_ratings)
+        )));
+    }
+
+    match response.finish_reason().as_deref() {
+        Some("SAFETY") | Some("PROHIBITED_CONTENT") | Some("BLOCKLIST") | Some("SPII") => {
+            let ratings = response
+                .candidates
+                .first()
+                .map(|c| blocked_categories(&c.safety_ratings))
+                .unwrap_or_default();
+            Err(AiError::Safety(format!(
+                "response was blocked: {}",
+                ratings
+            )))
+        }
+        Some("MAX_TOKENS") => Err(AiError::MaxTokens(
+            "response reached maxOutputTokens".to_string(),
+        )),
+        _ => Ok(()),
+    }
+}
+
+// ブロックの原因となったカテゴリ
+fn blocked_categories(ratings: &[SafetyRating]) -> String {
+    ratings
+        .iter()
+        .filter(|r| r.blocked || r.probability == "HIGH" || r.probability == "MEDIUM")
+        .map(|r| format!("{}({})", r.category, r.probability))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+fn to_response(model: String, result: String, response: &GenerateContentResponse) -> AiResponse {
+    AiResponse {
+        model,
+        result,
+        finish_reason: response.finish_reason(),
+        safety_ratings: response
+            .candidates
+            .first()
+            .map(|c| c.safety_ratings.clone())
+            .unwrap_or_default(),
+        usage: response.usage_metadata.as_ref().map(|u| Usage {
+            prompt_tokens: u.prompt_token_count,
+            output_tokens: u.candidates_token_count,
+            total_tokens: u.total_token_count,
+        }),
+    }
+}
+
+impl Provider for Gemini {
+    fn name(&self) -> &'static str {
+        "gemini"
+    }
+
+    async fn request(&self, request: &AiRequest) -> Result<AiResponse, AiError> {
+        let mut errors = Vec::new();
+
+        // 優先順にモデルを試し、失敗した場合は次のモデルへ切り替える
+        for model in self.available_models() {
+            let url = format!(
+                "{}/v1beta/models/{}:generateContent?key={}",
+                self.base_url, model, self.token
+            );
+
+            match inner_request(&self.http, &url, request).await {
+                Ok(response) => {
+                    let result = response.text();
+                    info!("{:?}", result);
+                    record_success(&model);
+                    if !errors.is_empty() {
+                        info!("fallback to model: {}, errors: {:?}", model, errors);
+                    }
+                    return Ok(to_response(model, result, &response));
+                }
+                Err(Failure::Fallback(e)) => {
+                    warn!("model {} failed, try next model: {:?}", model, e);
+                    record_failure(&model);
+                    errors.push((model, e));
+                }
+                Err(Failure::Fatal(e)) => {
+                    error!("error: {:?}", e);
+                    return Err(e);
+                }
+            }
+        }
+
+        // 全てのモデルがタイムアウトした場合は 504 として扱う
+        let msg = format!(
+            "all models failed: {}",
+            errors
+                .iter()
+                .map(|(model, e)| format!("{}: {}", model, e))
+                .collect::<Vec<String>>()
+                .join(", ")
+        );
+        if errors.iter().all(|(_, e)| matches!(e, AiError::Timeout(_))) {
+            Err(AiError::Timeout(msg))
+        } else {
+            Err(AiError::Upstream(msg))
+        }
+    }
+}
+
+// request to Gemini API
+// parse response
+pub async fn request(http: &Http, request: &AiRequest) -> Result<AiResponse, AiError> {
+    call(Gemini::from_env(http.clone())?, request).await
+}
+
+// generateContent / streamGenerateContent 共通のリクエストボディ
+fn request_body(request: &AiRequest) -> GenerateContentRequest {
+    let config = &request.config;
+    GenerateContentRequest {
+        system_instruction: request.system.as_deref().map(Content::system),
+        contents: request.turns().iter().map(Content::from_turn).collect(),
+        generation_config: GenerationConfig {
+            temperature: config.temperature,
+            max_output_tokens: config.max_output_tokens,
+            top_k: config.top_k,
+            top_p: config.top_p,
+            response_mime_type: request
+                .response_schema
+                .as_ref()
+                .map(|_| "application/json".to_string()),
+            response_schema: request.response_schema.clone(),
+        },
+    }
+}
+
+async fn inner_request(
+    http: &Http,
+    url: &str,
+    request: &AiRequest,
+) -> Result<GenerateContentResponse, Failure> {
+    // 一時的な失敗は Http::send 内で再試行される
+    let builder = http.client.post(url).json(&request_body(request));
+    let res = match http.send(builder).await {
+        Ok(v) => v,
+        // タイムアウトは次のモデルで回復しうる
+        Err(e @ AiError::Timeout(_)) => return Err(Failure::Fallback(e)),
+        Err(e) => return Err(Failure::Fatal(e)),
+    };
+
+    // 再試行後もクォータ超過・5xx の場合は次のモデルで回復しうる
+    let status = res.status();
+    if !status.is_success() {
+        let err = Http::upstream_error(res).await;
+        if status.as_u16() == 429 || status.is_server_error() {
+            return Err(Failure::Fallback(err));
         }
+        return Err(Failure::Fatal(err));
+    }
+
+    let response = match res.json::<GenerateContentResponse>().await {
+        Ok(v) => v,
         Err(e) => {
-            eprintln!("JSONのパースに失敗しました: {}", e);
-            Err(e.to_string())
+            error!("JSONのパースに失敗しました: {}", e);
+            return Err(Failure::Fatal(AiError::from(e)));
         }
+    };
+
+    // ブロック・打ち切りはモデルを変えても回復しない
+    check_finish(&response).map_err(Failure::Fatal)?;
+
+    // 候補が空の場合は次のモデルで回復しうる
+    if response.text().is_empty() {
+        return Err(Failure::Fallback(AiError::Upstream(format!(
+            "`candidates` にテキストが見つかりませんでした。 {:?}",
+            response
+        ))));
     }
+
+    Ok(response)
 }
@@ -0,0 +1,91 @@
+use firestore::{FirestoreQueryCursor, FirestoreQueryDirection};
+use tokio_stream::StreamExt;
+
+use crate::{
+    common::database::Database,
+    models::history::{History, HistoryQuery},
+};
+
+// AIへのリクエストの履歴の保存・取得
+
+const HISTORY: &str = "history";
+
+pub async fn create(db: &Database, history: &History) -> Result<(), String> {
+    db.create(HISTORY, &history.id, history.clone()).await
+}
+
+pub async fn get(db: &Database, id: &str) -> Result<Option<History>, String> {
+    db.read::<History>(HISTORY, id).await
+}
+
+/// ユーザーの履歴を新しい順に1ページ分取得する
+/// プロンプトタイプ・作成日の条件、並び順、件数はクエリで指定する (firestore.indexes.json の複合インデックスを使う)
+/// 文字列の条件 (`q`) はクエリで指定できないため、読み込みながら絞り込み、1ページ分が揃った時点で読み込みをやめる
+/// 戻り値は (ページの履歴, 次のページの cursor)
+pub async fn list(
+    db: &Database,
+    user_id: &str,
+    query: &HistoryQuery,
+) -> Result<(Vec<History>, Option<String>), String> {
+    let per_page = query.per_page();
+    let (from, to) = query.created_at_range();
+    let mut select = db
+        .client
+        .fluent()
+        .select()
+        .from(HISTORY)
+        .filter(|q| {
+            q.for_all([
+                q.field("user_id").eq(user_id),
+                query
+                    .prompt_type
+                    .as_ref()
+                    .and_then(|p| q.field("prompt_type").eq(p.as_str())),
+                from.as_ref()
+                    .and_then(|from| q.field("created_at").greater_than_or_equal(from.as_str())),
+                to.as_ref()
+                    .and_then(|to| q.field("created_at").less_than(to.as_str())),
+            ])
+        })
+        .order_by([("created_at", FirestoreQueryDirection::Descending)]);
+    if let Some(cursor) = &query.cursor {
+        select = select.start_at(FirestoreQueryCursor::AfterValue(vec![
+            cursor.as_str().into(),
+        ]));
+    }
+    // 次のページがあるかを判別するため、1件多く取得する
+    if query.q.is_none() {
+        select = select.limit(per_page as u32 + 1);
+    }
+
+    let mut stream = match select.obj::<History>().stream_query_with_errors().await {
+        Ok(stream) => stream,
+        Err(e) => return Err(format!("Failed to read documents: {}", e)),
+    };
+    let mut items = Vec::new();
+    while let Some(item) = stream.next().await {
+        let history = match item {
+            Ok(history) => history,
+            Err(e) => return Err(format!("Failed to read documents: {}", e)),
+        };
+        if !query.matches(&history) {
+            continue;
+        }
+        items.push(history);
+        if items.len() > per_page {
+            break;
+        }
+    }
+
+    let next_cursor = if items.len() > per_page {
+        items.truncate(per_page);
+        items.last().map(|h| h.created_at.clone())
+    } else {
+        None
+    };
+    Ok((items, next_cursor))
+}
+
+pub async fn delete(db: &Database, id: &str) -> Result<(), String> {
+    db.delete(HISTORY, id).await
+}
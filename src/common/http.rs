@@ -0,0 +1,134 @@
+use std::time::Duration;
+
+use log::warn;
+use reqwest::{RequestBuilder, Response, StatusCode, header::RETRY_AFTER};
+
+use crate::common::provider::{AiError, env_or};
+
+/// 上流AIへのリクエストに使う共有HTTPクライアント
+/// コネクションプールを使い回すため、アプリケーション状態として保持する
+#[derive(Debug, Clone)]
+pub struct Http {
+    pub client: reqwest::Client,
+    // 最大リトライ回数 (初回を含まない)
+    max_retries: u32,
+    // バックオフの基準時間
+    base_delay: Duration,
+    // バックオフの上限
+    max_delay: Duration,
+}
+
+impl Http {
+    // 環境変数から設定を読み込む
+    pub fn from_env() -> Self {
+        let secs = |key: &str, default: u64| {
+            Duration::from_secs(env_or(key, "").parse().unwrap_or(default))
+        };
+        let millis = |key: &str, default: u64| {
+            Duration::from_millis(env_or(key, "").parse().unwrap_or(default))
+        };
+
+        let client = match reqwest::Client::builder()
+            .connect_timeout(secs("AI_CONNECT_TIMEOUT_SECS", 10))
+            .read_timeout(secs("AI_READ_TIMEOUT_SECS", 180))
+            .build()
+        {
+            Ok(client) => client,
+            Err(e) => panic!("Failed to create HTTP client: {}", e),
+        };
+
+        Http {
+            client,
+            max_retries: env_or("AI_MAX_RETRIES", "").parse().unwrap_or(3),
+            base_delay: millis("AI_RETRY_BASE_MS", 500),
+            max_delay: millis("AI_RETRY_MAX_MS", 30_000),
+        }
+    }
+
+    /// リクエストを送信する
+    /// 一時的な失敗 (429, 502, 503, 504, 接続エラー, タイムアウト) はバックオフを挟んで再試行する
+    /// 再試行しても失敗したステータスはそのまま返し、呼び出し側で扱う
+    pub async fn send(&self, builder: RequestBuilder) -> Result<Response, AiError> {
+        let mut attempt = 0;
+        loop {
+            let request = match builder.try_clone() {
+                Some(request) => request,
+                None => return Err(AiError::BadRequest("request is not clonable".to_string())),
+            };
+
+            match request.send().await {
+                Ok(res) => {
+                    if attempt >= self.max_retries || !is_transient(res.status()) {
+                        return Ok(res);
+                    }
+                    let delay = retry_after(&res)
+                        .map(|d| d.min(self.max_delay))
+                        .unwrap_or_else(|| self.backoff(attempt));
+                    warn!(
+                        "upstream returned {}, retry after {:?} ({}/{})",
+                        res.status(),
+                        delay,
+                        attempt + 1,
+                        self.max_retries
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                Err(e) => {
+                    if attempt >= self.max_retries || !(e.is_timeout() || e.is_connect()) {
+                        return Err(AiError::from(e));
+                    }
+                    let delay = self.backoff(attempt);
+                    warn!(
+                        "upstream request failed: {}, retry after {:?} ({}/{})",
+                        e,
+                        delay,
+                        attempt + 1,
+                        self.max_retries
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+            }
+            attempt += 1;
+        }
+    }
+
+    /// 失敗したレスポンスを上流のエラーとして扱う
+    pub async fn upstream_error(res: Response) -> AiError {
+        let status = res.status();
+        let text = res.text().await.unwrap_or_default();
+        AiError::Upstream(format!("{}: {}", status, text))
+    }
+
+    // 指数バックオフ (full jitter)
+    // 0 から min(max_delay, base_delay * 2^attempt) の範囲でランダムに待つ
+    fn backoff(&self, attempt: u32) -> Duration {
+        let cap = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+        let millis = cap.as_millis() as u64;
+        Duration::from_millis(rand::random_range(0..=millis))
+    }
+}
+
+// 再試行で回復しうるステータス
+fn is_transient(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+// Retry-After ヘッダ (秒数 または HTTP-date) を待ち時間に変換する
+fn retry_after(res: &Response) -> Option<Duration> {
+    let value = res.headers().get(RETRY_AFTER)?.to_str().ok()?;
+    if let Ok(secs) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value.trim()).ok()?;
+    let secs = (date.timestamp() - chrono::Utc::now().timestamp()).max(0);
+    Some(Duration::from_secs(secs as u64))
+}
@@ -0,0 +1,157 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex},
+};
+
+use log::info;
+use tokio::{sync::Semaphore, task::JoinHandle};
+
+use crate::{
+    common::{database::Database, provider::env_or},
+    models::job::{Job, JobStatus},
+};
+
+// 非同期ジョブの保存と実行
+// ジョブはデータベースに保存し、インスタンスの再起動後も状態を確認・再開できるようにする
+
+const JOBS: &str = "jobs";
+
+pub async fn get(db: &Database, id: &str) -> Result<Option<Job>, String> {
+    db.read::<Job>(JOBS, id).await
+}
+
+pub async fn create(db: &Database, job: &Job) -> Result<(), String> {
+    db.create(JOBS, &job.id, job.clone()).await
+}
+
+/// 実行中の状態を保存する
+/// 取り消されたジョブには書き込まず false を返す
+pub async fn save(db: &Database, job: &mut Job) -> Result<bool, String> {
+    job.updated_at = chrono::Utc::now().to_rfc3339();
+    let saved = db
+        .update_with(JOBS, &job.id, |current: Job| {
+            (current.status != JobStatus::Cancelled).then(|| job.clone())
+        })
+        .await?;
+    Ok(saved.is_some())
+}
+
+/// 実行待ち、または更新が `stale_seconds` 秒途絶えた実行中のジョブを実行中にする
+/// 他のインスタンスが先に実行を始めた、または取り消されたジョブは None
+pub async fn start(db: &Database, id: &str, stale_seconds: i64) -> Result<Option<Job>, String> {
+    db.update_with(JOBS, id, |mut job: Job| {
+        let startable = job.status == JobStatus::Queued
+            || (job.status == JobStatus::Running && job.is_stale(stale_seconds));
+        if !startable {
+            return None;
+        }
+        job.status = JobStatus::Running;
+        job.partial.clear();
+        job.progress = None;
+        job.updated_at = chrono::Utc::now().to_rfc3339();
+        Some(job)
+    })
+    .await
+}
+
+/// 実行待ちのジョブの更新日時のみを更新する
+/// 実行待ちでなくなった場合は false
+pub async fn touch(db: &Database, id: &str) -> Result<bool, String> {
+    let touched = db
+        .update_with(JOBS, id, |mut job: Job| {
+            if job.status != JobStatus::Queued {
+                return None;
+            }
+            job.updated_at = chrono::Utc::now().to_rfc3339();
+            Some(job)
+        })
+        .await?;
+    Ok(touched.is_some())
+}
+
+/// 終了していないジョブを取り消す
+/// 既に終了している場合は None
+pub async fn cancel(db: &Database, id: &str) -> Result<Option<Job>, String> {
+    db.update_with(JOBS, id, |mut job: Job| {
+        if job.status.is_finished() {
+            return None;
+        }
+        job.status = JobStatus::Cancelled;
+        job.updated_at = chrono::Utc::now().to_rfc3339();
+        Some(job)
+    })
+    .await
+}
+
+/// 実行待ち・実行中のジョブ
+pub async fn unfinished(db: &Database) -> Result<Vec<Job>, String> {
+    let mut jobs = Vec::new();
+    for status in [JobStatus::Queued, JobStatus::Running] {
+        jobs.extend(db.read_by::<Job>(JOBS, "status", status.as_str()).await?);
+    }
+    jobs.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+    Ok(jobs)
+}
+
+/// ジョブの実行キュー
+/// 同時に実行するジョブの数を JOB_CONCURRENCY で制限する
+pub struct JobQueue {
+    semaphore: Arc<Semaphore>,
+    running: Arc<Mutex<HashMap<String, JoinHandle<()>>>>,
+}
+
+impl JobQueue {
+    pub fn from_env() -> Self {
+        let concurrency = env_or("JOB_CONCURRENCY", "2")
+            .parse::<usize>()
+            .ok()
+            .filter(|v| *v > 0)
+            .unwrap_or(2);
+        info!("job concurrency: {}", concurrency);
+        JobQueue {
+            semaphore: Arc::new(Semaphore::new(concurrency)),
+            running: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// ジョブを実行待ちに追加する
+    /// 実行枠が空くまで `waiting` を並行して進め、空いたら `task` を実行する
+    pub fn spawn<F, W>(&self, id: &str, waiting: W, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+        W: Future<Output = ()> + Send + 'static,
+    {
+        let semaphore = self.semaphore.clone();
+        let running = self.running.clone();
+        let key = id.to_string();
+        // 終了時の削除が登録より先に行われないよう、登録が終わるまでロックを保持する
+        let mut guard = self.running.lock().unwrap();
+        let handle = tokio::spawn(async move {
+            let permit = tokio::select! {
+                permit = semaphore.clone().acquire_owned() => permit,
+                _ = waiting => semaphore.acquire_owned().await,
+            };
+            if let Ok(_permit) = permit {
+                task.await;
+            }
+            running.lock().unwrap().remove(&key);
+        });
+        guard.insert(id.to_string(), handle);
+    }
+
+    /// 実行待ち・実行中のジョブを中断する
+    /// このインスタンスで実行していない場合は false
+    pub async fn cancel(&self, id: &str) -> bool {
+        let handle = self.running.lock().unwrap().remove(id);
+        match handle {
+            Some(handle) => {
+                handle.abort();
+                // 中断が完了するまで待ち、以降に状態が書き込まれないようにする
+                let _ = handle.await;
+                true
+            }
+            None => false,
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use log::{error, info};
+use serde_json::{Value, json};
+
+use crate::common::{
+    http::Http,
+    openai::{OpenAi, messages},
+    provider::{AiError, AiRequest, AiResponse, Provider, env_or},
+};
+
+/// ローカル/セルフホストモデルのAPI形式
+#[derive(Debug, Clone, PartialEq)]
+pub enum LocalFormat {
+    // Ollama の /api/chat
+    Ollama,
+    // OpenAI 互換の /v1/chat/completions (vLLM, LM Studio, llama.cpp server など)
+    OpenAi,
+}
+
+impl LocalFormat {
+    pub fn parse(s: &str) -> Result<Self, AiError> {
+        match s {
+            "ollama" => Ok(LocalFormat::Ollama),
+            "openai" => Ok(LocalFormat::OpenAi),
+            _ => Err(AiError::Config(format!(
+                "LOCAL_API_FORMAT is not supported: {}",
+                s
+            ))),
+        }
+    }
+}
+
+/// 外部に送信せず、社内などに配置したモデルへリクエストする
+#[derive(Debug, Clone)]
+pub struct Local {
+    http: Http,
+    base_url: String,
+    model: String,
+    token: String,
+    format: LocalFormat,
+}
+
+impl Local {
+    pub fn new(
+        http: Http,
+        base_url: String,
+        model: String,
+        token: String,
+        format: LocalFormat,
+    ) -> Self {
+        Self {
+            http,
+            base_url,
+            model,
+            token,
+            format,
+        }
+    }
+
+    // 環境変数から設定を読み込む
+    // トークンは任意 (認証を挟んだプロキシ経由の場合のみ)
+    pub fn from_env(http: Http) -> Result<Self, AiError> {
+        let model = env_or("LOCAL_MODEL", "");
+        if model.is_empty() {
+            return Err(AiError::Config("LOCAL_MODEL is empty".to_string()));
+        }
+
+        let format = LocalFormat::parse(&env_or("LOCAL_API_FORMAT", "ollama"))?;
+        let base_url = env_or("LOCAL_BASE_URL", "http://localhost:11434");
+        let token = env_or("LOCAL_API_KEY", "");
+        Ok(Self::new(http, base_url, model, token, format))
+    }
+
+    // Ollama の /api/chat へリクエストする
+    async fn request_ollama(&self, request: &AiRequest) -> Result<AiResponse, AiError> {
+        let url = format!("{}/api/chat", self.base_url);
+
+        let config = &request.config;
+        let mut body = json!({
+            "model": self.model,
+            "stream": false,
+            "options": {
+                "temperature": config.temperature,
+                "num_predict": config.max_output_tokens,
+                "top_p": config.top_p,
+                "top_k": config.top_k,
+            },
+            "messages": messages(request),
+        });
+        // スキーマは system の指示で伝え、JSON での出力のみ指定する
+        if request.response_schema.is_some() {
+            body["format"] = json!("json");
+        }
+
+        let mut builder = self.http.client.post(url).json(&body);
+        if !self.token.is_empty() {
+            builder = builder.bearer_auth(&self.token);
+        }
+        let res = self.http.send(builder).await?;
+        if !res.status().is_success() {
+            return Err(Http::upstream_error(res).await);
+        }
+
+        let value = match res.json::<Value>().await {
+            Ok(v) => v,
+            Err(e) => {
+                error!("JSONのパースに失敗しました: {}", e);
+                return Err(AiError::from(e));
+            }
+        };
+        info!("{:?}", value);
+
+        match value
+            .get("message")
+            .and_then(|message| message.get("content"))
+            .and_then(|content| content.as_str())
+        {
+            Some(text) => Ok(AiResponse {
+                finish_reason: value["done_reason"].as_str().map(|s| s.to_string()),
+                ..AiResponse::new(
+                    value["model"].as_str().unwrap_or(&self.model).to_string(),
+                    text.to_string(),
+                )
+            }),
+            None => Err(AiError::Upstream(format!(
+                "`message.content` が見つかりませんでした。 {}",
+                value
+            ))),
+        }
+    }
+}
+
+impl Provider for Local {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    async fn request(&self, request: &AiRequest) -> Result<AiResponse, AiError> {
+        match self.format {
+            LocalFormat::Ollama => self.request_ollama(request).await,
+            LocalFormat::OpenAi => {
+                OpenAi::new(
+                    self.http.clone(),
+                    self.base_url.clone(),
+                    self.model.clone(),
+                    self.token.clone(),
+                )
+                .request(request)
+                .await
+            }
+        }
+    }
+}
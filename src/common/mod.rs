@@ -1,2 +1,16 @@
+pub mod claude;
+pub mod conversation;
 pub mod database;
+pub mod diff;
+pub mod export;
 pub mod gemini;
+pub mod history;
+pub mod http;
+pub mod job;
+pub mod local;
+pub mod openai;
+pub mod provider;
+pub mod render;
+pub mod state;
+pub mod template;
+pub mod tokens;
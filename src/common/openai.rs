@@ -0,0 +1,150 @@
+use log::{error, info};
+use serde_json::{Value, json};
+
+use crate::common::{
+    http::Http,
+    provider::{AiError, AiRequest, AiResponse, Provider, Usage, env_or},
+};
+
+/// OpenAI Chat Completions API
+#[derive(Debug, Clone)]
+pub struct OpenAi {
+    http: Http,
+    base_url: String,
+    model: String,
+    token: String,
+}
+
+impl OpenAi {
+    pub fn new(http: Http, base_url: String, model: String, token: String) -> Self {
+        Self {
+            http,
+            base_url,
+            model,
+            token,
+        }
+    }
+
+    // 環境変数から設定を読み込む
+    pub fn from_env(http: Http) -> Result<Self, AiError> {
+        let model = env_or("OPENAI_MODEL", "");
+        let token = env_or("OPENAI_API_KEY", "");
+        if model.is_empty() || token.is_empty() {
+            return Err(AiError::Config(
+                "OPENAI_MODEL or OPENAI_API_KEY is empty".to_string(),
+            ));
+        }
+
+        let base_url = env_or("OPENAI_BASE_URL", "https://api.openai.com");
+        Ok(Self::new(http, base_url, model, token))
+    }
+}
+
+/// Chat Completions 形式の messages
+/// 役割・指示は system ロールのメッセージとして先頭に置く
+/// Ollama の /api/chat も同じ形式
+pub fn messages(request: &AiRequest) -> Value {
+    let mut messages = Vec::new();
+    if let Some(system) = &request.system {
+        messages.push(json!({
+            "role": "system",
+            "content": system
+        }));
+    }
+    for turn in request.turns() {
+        messages.push(json!({
+            "role": turn.role.as_chat_role(),
+            "content": turn.text
+        }));
+    }
+    json!(messages)
+}
+
+impl Provider for OpenAi {
+    fn name(&self) -> &'static str {
+        "chatgpt"
+    }
+
+    async fn request(&self, request: &AiRequest) -> Result<AiResponse, AiError> {
+        let url = format!("{}/v1/chat/completions", self.base_url);
+
+        // Chat Completions には top_k がない
+        let config = &request.config;
+        let mut body = json!({
+            "model": self.model,
+            "temperature": config.temperature,
+            "max_tokens": config.max_output_tokens,
+            "top_p": config.top_p,
+            "messages": messages(request),
+        });
+        // スキーマは system の指示で伝え、JSON モードのみ指定する
+        if request.response_schema.is_some() {
+            body["response_format"] = json!({"type": "json_object"});
+        }
+
+        // OpenAI 互換のローカルサーバーではトークン不要の場合がある
+        let mut builder = self.http.client.post(url).json(&body);
+        if !self.token.is_empty() {
+            builder = builder.bearer_auth(&self.token);
+        }
+        let res = self.http.send(builder).await?;
+        if !res.status().is_success() {
+            return Err(Http::upstream_error(res).await);
+        }
+
+        let value = match res.json::<Value>().await {
+            Ok(v) => v,
+            Err(e) => {
+                error!("JSONのパースに失敗しました: {}", e);
+                return Err(AiError::from(e));
+            }
+        };
+        info!("{:?}", value);
+
+        // choices[0].message.content を取得する
+        let text = value
+            .get("choices")
+            .and_then(|choices| choices.as_array())
+            .and_then(|arr| arr.first())
+            .and_then(|choice| choice.get("message"))
+            .and_then(|message| message.get("content"))
+            .and_then(|content| content.as_str());
+
+        // 出力上限による打ち切り・コンテンツフィルタは個別のエラーとして扱う
+        let finish_reason = value["choices"][0]["finish_reason"]
+            .as_str()
+            .map(|s| s.to_string());
+        match finish_reason.as_deref() {
+            Some("length") => {
+                return Err(AiError::MaxTokens(
+                    "response reached max_tokens".to_string(),
+                ));
+            }
+            Some("content_filter") => {
+                return Err(AiError::Safety(
+                    "response was blocked by content filter".to_string(),
+                ));
+            }
+            _ => (),
+        }
+
+        match text {
+            Some(text) => Ok(AiResponse {
+                finish_reason,
+                usage: value.get("usage").map(|usage| Usage {
+                    prompt_tokens: usage["prompt_tokens"].as_u64().unwrap_or_default() as u32,
+                    output_tokens: usage["completion_tokens"].as_u64().unwrap_or_default() as u32,
+                    total_tokens: usage["total_tokens"].as_u64().unwrap_or_default() as u32,
+                }),
+                ..AiResponse::new(
+                    value["model"].as_str().unwrap_or(&self.model).to_string(),
+                    text.to_string(),
+                )
+            }),
+            None => Err(AiError::Upstream(format!(
+                "`choices[0].message.content` が見つかりませんでした。 {}",
+                value
+            ))),
+        }
+    }
+}
@@ -0,0 +1,693 @@
+use std::fmt::Display;
+
+use axum::http::StatusCode;
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    common::{claude::Claude, gemini, http::Http, local::Local, openai::OpenAi},
+    models::gemini::SafetyRating,
+};
+
+/// 生成パラメータ
+/// プロンプトタイプごとの既定値を持ち、リクエストごとに上書きできる
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GenerationConfig {
+    pub temperature: f32,
+    pub max_output_tokens: u32,
+    pub top_p: f32,
+    pub top_k: u32,
+    // リクエストで明示的に指定された値
+    // Claude は temperature と top_p を同時に指定できないため、指定された場合のみ送信する
+    #[serde(skip)]
+    pub overrides: GenerationOverrides,
+}
+
+impl Default for GenerationConfig {
+    fn default() -> Self {
+        GenerationConfig {
+            temperature: 0.1,
+            max_output_tokens: 8192,
+            top_p: 0.1,
+            top_k: 1,
+            overrides: GenerationOverrides::default(),
+        }
+    }
+}
+
+/// リクエストボディの `generation_config` で指定する上書き値
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GenerationOverrides {
+    pub temperature: Option<f32>,
+    pub max_output_tokens: Option<u32>,
+    pub top_p: Option<f32>,
+    pub top_k: Option<u32>,
+}
+
+impl GenerationConfig {
+    /// 上書き値を許容範囲で検証して適用する
+    pub fn with_overrides(mut self, overrides: &GenerationOverrides) -> Result<Self, AiError> {
+        if let Some(v) = overrides.temperature {
+            if !(0.0..=2.0).contains(&v) {
+                return Err(AiError::BadRequest(format!(
+                    "temperature must be between 0.0 and 2.0: {}",
+                    v
+                )));
+            }
+            self.temperature = v;
+        }
+        if let Some(v) = overrides.max_output_tokens {
+            if !(1..=65536).contains(&v) {
+                return Err(AiError::BadRequest(format!(
+                    "max_output_tokens must be between 1 and 65536: {}",
+                    v
+                )));
+            }
+            self.max_output_tokens = v;
+        }
+        if let Some(v) = overrides.top_p {
+            if !(0.0..=1.0).contains(&v) {
+                return Err(AiError::BadRequest(format!(
+                    "top_p must be between 0.0 and 1.0: {}",
+                    v
+                )));
+            }
+            self.top_p = v;
+        }
+        if let Some(v) = overrides.top_k {
+            if !(1..=100).contains(&v) {
+                return Err(AiError::BadRequest(format!(
+                    "top_k must be between 1 and 100: {}",
+                    v
+                )));
+            }
+            self.top_k = v;
+        }
+        self.overrides = overrides.clone();
+        Ok(self)
+    }
+}
+
+/// 会話の発言者
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    User,
+    Model,
+}
+
+impl Role {
+    /// Claude・OpenAI 形式のロール名
+    pub fn as_chat_role(&self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Model => "assistant",
+        }
+    }
+}
+
+/// 会話の1ターン
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Turn {
+    pub role: Role,
+    pub text: String,
+}
+
+impl Turn {
+    pub fn user(text: &str) -> Self {
+        Turn {
+            role: Role::User,
+            text: text.to_string(),
+        }
+    }
+
+    pub fn model(text: &str) -> Self {
+        Turn {
+            role: Role::Model,
+            text: text.to_string(),
+        }
+    }
+}
+
+/// AIプロバイダへのリクエスト
+#[derive(Debug, Clone)]
+pub struct AiRequest {
+    // 役割・指示 (Gemini の systemInstruction、Claude の system など)
+    pub system: Option<String>,
+    // これまでの会話 (content より前のターン)
+    pub history: Vec<Turn>,
+    // ユーザーの入力
+    pub content: String,
+    pub config: GenerationConfig,
+    // 指定した場合は JSON で出力させる (Gemini の responseSchema)
+    pub response_schema: Option<Value>,
+}
+
+impl AiRequest {
+    pub fn new(content: String, config: GenerationConfig) -> Self {
+        AiRequest {
+            system: None,
+            history: Vec::new(),
+            content,
+            config,
+            response_schema: None,
+        }
+    }
+
+    pub fn with_response_schema(mut self, schema: Value) -> Self {
+        self.response_schema = Some(schema);
+        self
+    }
+
+    pub fn with_system(mut self, system: String) -> Self {
+        self.system = Some(system);
+        self
+    }
+
+    pub fn with_history(mut self, history: Vec<Turn>) -> Self {
+        self.history = history;
+        self
+    }
+
+    /// 出力トークン数を上限に収める
+    pub fn with_output_limit(mut self, limit: u32) -> Self {
+        self.config.max_output_tokens = self.config.max_output_tokens.min(limit);
+        self
+    }
+
+    /// 会話の全ターン (最後が content)
+    pub fn turns(&self) -> Vec<Turn> {
+        let mut turns = self.history.clone();
+        turns.push(Turn::user(&self.content));
+        turns
+    }
+}
+
+/// AIプロバイダからの応答
+/// どのプロバイダでも同じ形で扱えるように統一する
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AiResponse {
+    // 実際に応答したモデル名
+    pub model: String,
+    // モデルの出力 (markdown)
+    pub result: String,
+    // 終了理由 (プロバイダの表記のまま)
+    pub finish_reason: Option<String>,
+    // 安全性評価 (Gemini のみ)
+    pub safety_ratings: Vec<SafetyRating>,
+    // トークン使用量
+    pub usage: Option<Usage>,
+}
+
+impl AiResponse {
+    pub fn new(model: String, result: String) -> Self {
+        AiResponse {
+            model,
+            result,
+            finish_reason: None,
+            safety_ratings: vec![],
+            usage: None,
+        }
+    }
+}
+
+/// トークン使用量
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Usage {
+    pub prompt_tokens: u32,
+    pub output_tokens: u32,
+    pub total_tokens: u32,
+}
+
+impl Usage {
+    /// 複数回のリクエストの使用量を合算する
+    pub fn add(&mut self, other: &Usage) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.output_tokens += other.output_tokens;
+        self.total_tokens += other.total_tokens;
+    }
+}
+
+/// AIプロバイダ呼び出し時のエラー
+/// 呼び出し側で適切なHTTPステータスを返せるように分類する
+#[derive(Debug, Clone)]
+pub enum AiError {
+    // リクエストの誤り、未対応の target_ai など
+    BadRequest(String),
+    // 未対応の prompt_type
+    NotFound(String),
+    // APIキーやモデルが設定されていない
+    Config(String),
+    // 上流APIがエラーを返した、または応答が解析できない
+    Upstream(String),
+    // 上流APIがタイムアウトした
+    Timeout(String),
+    // 安全性フィルタにより応答がブロックされた
+    Safety(String),
+    // 出力トークン上限に達し、応答が途中で打ち切られた
+    MaxTokens(String),
+}
+
+impl AiError {
+    /// クライアントへ返すHTTPステータス
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AiError::NotFound(_) => StatusCode::NOT_FOUND,
+            AiError::Config(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AiError::Upstream(_) => StatusCode::BAD_GATEWAY,
+            AiError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            AiError::Safety(_) | AiError::MaxTokens(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        }
+    }
+
+    /// UI がエラーの種類を判別するためのコード
+    pub fn code(&self) -> &'static str {
+        match self {
+            AiError::BadRequest(_) => "bad_request",
+            AiError::NotFound(_) => "not_found",
+            AiError::Config(_) => "config",
+            AiError::Upstream(_) => "upstream",
+            AiError::Timeout(_) => "timeout",
+            AiError::Safety(_) => "safety",
+            AiError::MaxTokens(_) => "max_tokens",
+        }
+    }
+}
+
+impl Display for AiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AiError::BadRequest(msg) => write!(f, "bad request: {}", msg),
+            AiError::NotFound(msg) => write!(f, "not found: {}", msg),
+            AiError::Config(msg) => write!(f, "config error: {}", msg),
+            AiError::Upstream(msg) => write!(f, "upstream error: {}", msg),
+            AiError::Timeout(msg) => write!(f, "upstream timeout: {}", msg),
+            AiError::Safety(msg) => write!(f, "blocked by safety filter: {}", msg),
+            AiError::MaxTokens(msg) => write!(f, "output was truncated: {}", msg),
+        }
+    }
+}
+
+impl From<reqwest::Error> for AiError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            AiError::Timeout(e.to_string())
+        } else {
+            AiError::Upstream(e.to_string())
+        }
+    }
+}
+
+/// AIプロバイダの共通インターフェース
+/// プロンプトを受け取り、モデルの応答を返す
+pub trait Provider {
+    /// `target_ai` で指定される名前
+    fn name(&self) -> &'static str;
+
+    /// プロンプトを送信し、応答を取得する
+    fn request(
+        &self,
+        request: &AiRequest,
+    ) -> impl Future<Output = Result<AiResponse, AiError>> + Send;
+}
+
+/// 対応している `target_ai` の一覧
+pub const SUPPORTED: [&str; 4] = ["gemini", "claude", "chatgpt", "local"];
+
+/// `target_ai` ごとの出力トークン数の上限 (既定のモデルの上限)
+/// プロンプトタイプの既定値や上書き値が上限を超える場合は上限に収める
+pub fn max_output_tokens(target_ai: &str) -> u32 {
+    match target_ai {
+        // gpt-4o
+        "chatgpt" => 16384,
+        // claude-sonnet-4-5
+        "claude" => 64000,
+        _ => 65536,
+    }
+}
+
+/// `target_ai` に応じたプロバイダへリクエストを振り分ける
+pub async fn request(
+    http: &Http,
+    target_ai: &str,
+    request: &AiRequest,
+) -> Result<AiResponse, AiError> {
+    let request = &request
+        .clone()
+        .with_output_limit(max_output_tokens(target_ai));
+    match target_ai {
+        "gemini" => gemini::request(http, request).await,
+        "claude" => call(Claude::from_env(http.clone())?, request).await,
+        "chatgpt" => call(OpenAi::from_env(http.clone())?, request).await,
+        "local" => call(Local::from_env(http.clone())?, request).await,
+        _ => Err(AiError::BadRequest(format!(
+            "target_ai is not supported: {}, supported: [{}]",
+            target_ai,
+            SUPPORTED.join(", ")
+        ))),
+    }
+}
+
+/// プロバイダへリクエストを送信する
+pub async fn call<P: Provider>(provider: P, request: &AiRequest) -> Result<AiResponse, AiError> {
+    info!("request to provider: {}", provider.name());
+    provider.request(request).await
+}
+
+/// 環境変数を取得する。空文字列は未設定として扱う
+pub fn env_or(key: &str, default: &str) -> String {
+    match std::env::var(key) {
+        Ok(v) if !v.is_empty() => v,
+        _ => default.to_string(),
+    }
+}
+
+#[cfg(test)]
+// ローカルのモックサーバーに向けて各プロバイダの応答解析を確認する
+mod tests {
+    use axum::{
+        Json, Router, extract::Path, http::StatusCode, response::IntoResponse, routing::post,
+    };
+    use serde_json::json;
+
+    use super::*;
+    use crate::common::{gemini::Gemini, local::LocalFormat};
+
+    fn prompt() -> AiRequest {
+        AiRequest::new("prompt".to_string(), GenerationConfig::default())
+    }
+
+    // モックサーバーを起動し、ベースURLを返す
+    async fn serve(app: Router) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        format!("http://{}", addr)
+    }
+
+    // 固定のレスポンスを返すモックサーバーを起動し、ベースURLを返す
+    async fn mock_server(path: &'static str, response: Value) -> String {
+        serve(Router::new().route(
+            path,
+            post(move |Json(_): Json<Value>| {
+                let response = response.clone();
+                async move { Json(response) }
+            }),
+        ))
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_gemini() {
+        let base_url = mock_server(
+            "/v1beta/models/{model}",
+            json!({
+                "candidates": [{
+                    "content": {"parts": [{"text": "hello "}, {"text": "gemini"}]},
+                    "finishReason": "STOP"
+                }],
+                "usageMetadata": {"promptTokenCount": 3, "candidatesTokenCount": 2, "totalTokenCount": 5}
+            }),
+        )
+        .await;
+        let provider = Gemini::new(
+            Http::from_env(),
+            base_url,
+            vec!["gemini-test".to_string()],
+            "token".to_string(),
+        );
+        let response = provider.request(&prompt()).await.unwrap();
+        assert_eq!(response.model, "gemini-test");
+        assert_eq!(response.result, "hello gemini");
+        assert_eq!(response.finish_reason.as_deref(), Some("STOP"));
+        assert_eq!(response.usage.unwrap().total_tokens, 5);
+    }
+
+    #[tokio::test]
+    async fn test_gemini_finish_reason() {
+        let base_url = mock_server(
+            "/v1beta/models/{model}",
+            json!({
+                "candidates": [{
+                    "content": {"parts": [{"text": "partial"}]},
+                    "finishReason": "MAX_TOKENS"
+                }],
+                "usageMetadata": {"promptTokenCount": 10, "candidatesTokenCount": 8192, "totalTokenCount": 8202}
+            }),
+        )
+        .await;
+        let provider = Gemini::new(
+            Http::from_env(),
+            base_url.clone(),
+            vec!["gemini-max-tokens".to_string()],
+            "token".to_string(),
+        );
+        let err = provider.request(&prompt()).await.unwrap_err();
+        assert_eq!(err.code(), "max_tokens");
+
+        let base_url = mock_server(
+            "/v1beta/models/{model}",
+            json!({
+                "candidates": [{
+                    "finishReason": "SAFETY",
+                    "safetyRatings": [{"category": "HARM_CATEGORY_HARASSMENT", "probability": "HIGH", "blocked": true}]
+                }]
+            }),
+        )
+        .await;
+        let provider = Gemini::new(
+            Http::from_env(),
+            base_url,
+            vec!["gemini-safety".to_string()],
+            "token".to_string(),
+        );
+        let err = provider.request(&prompt()).await.unwrap_err();
+        assert_eq!(err.code(), "safety");
+        assert_eq!(err.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn test_gemini_fallback() {
+        // 先頭のモデルは 503、次のモデルは候補なし、最後のモデルが応答する
+        let app = Router::new().route(
+            "/v1beta/models/{model}",
+            post(|Path(model): Path<String>| async move {
+                if model.starts_with("gemini-unavailable") {
+                    (
+                        StatusCode::SERVICE_UNAVAILABLE,
+                        [("retry-after", "0")],
+                        Json(json!({})),
+                    )
+                        .into_response()
+                } else if model.starts_with("gemini-empty") {
+                    Json(json!({"candidates": []})).into_response()
+                } else {
+                    Json(json!({
+                        "candidates": [{"content": {"parts": [{"text": "fallback"}]}}]
+                    }))
+                    .into_response()
+                }
+            }),
+        );
+        let base_url = serve(app).await;
+
+        let provider = Gemini::new(
+            Http::from_env(),
+            base_url,
+            vec![
+                "gemini-unavailable".to_string(),
+                "gemini-empty".to_string(),
+                "gemini-ok".to_string(),
+            ],
+            "token".to_string(),
+        );
+        let response = provider.request(&prompt()).await.unwrap();
+        assert_eq!(response.model, "gemini-ok");
+        assert_eq!(response.result, "fallback");
+    }
+
+    #[tokio::test]
+    async fn test_retry() {
+        // 1回目は 429 を返し、2回目で成功する
+        let count = std::sync::Arc::new(std::sync::atomic::AtomicU32::new(0));
+        let app = Router::new().route(
+            "/v1/chat/completions",
+            post(move || {
+                let count = count.clone();
+                async move {
+                    if count.fetch_add(1, std::sync::atomic::Ordering::SeqCst) == 0 {
+                        (
+                            StatusCode::TOO_MANY_REQUESTS,
+                            [("retry-after", "0")],
+                            Json(json!({})),
+                        )
+                            .into_response()
+                    } else {
+                        Json(json!({
+                            "model": "gpt-test",
+                            "choices": [{"message": {"content": "retried"}}]
+                        }))
+                        .into_response()
+                    }
+                }
+            }),
+        );
+        let base_url = serve(app).await;
+
+        let provider = OpenAi::new(
+            Http::from_env(),
+            base_url,
+            "gpt-test".to_string(),
+            "token".to_string(),
+        );
+        let response = provider.request(&prompt()).await.unwrap();
+        assert_eq!(response.result, "retried");
+    }
+
+    #[tokio::test]
+    async fn test_gemini_stream() {
+        let app = Router::new().route(
+            "/v1beta/models/{model}",
+            post(|| async {
+                concat!(
+                    "data: {\"candidates\": [{\"content\": {\"parts\": [{\"text\": \"hello \"}]}}]}\r\n\r\n",
+                    "data: {\"candidates\": [{\"content\": {\"parts\": [{\"text\": \"stream\"}]}}]}\r\n\r\n",
+                )
+            }),
+        );
+        let base_url = serve(app).await;
+
+        let provider = Gemini::new(
+            Http::from_env(),
+            base_url,
+            vec!["gemini-stream-test".to_string()],
+            "token".to_string(),
+        );
+        let (tx, mut rx) = tokio::sync::mpsc::channel(8);
+        let response = provider.request_stream(&prompt(), tx).await.unwrap();
+        assert_eq!(response.result, "hello stream");
+        assert_eq!(rx.recv().await.unwrap(), "hello ");
+        assert_eq!(rx.recv().await.unwrap(), "stream");
+    }
+
+    #[tokio::test]
+    async fn test_claude() {
+        let base_url = mock_server(
+            "/v1/messages",
+            json!({
+                "model": "claude-test",
+                "content": [{"type": "text", "text": "hello claude"}]
+            }),
+        )
+        .await;
+        let provider = Claude::new(
+            Http::from_env(),
+            base_url,
+            "claude-test".to_string(),
+            "token".to_string(),
+        );
+        let response = provider.request(&prompt()).await.unwrap();
+        assert_eq!(response.model, "claude-test");
+        assert_eq!(response.result, "hello claude");
+    }
+
+    #[tokio::test]
+    async fn test_openai() {
+        let base_url = mock_server(
+            "/v1/chat/completions",
+            json!({
+                "model": "gpt-test",
+                "choices": [{"message": {"role": "assistant", "content": "hello openai"}}]
+            }),
+        )
+        .await;
+        let provider = OpenAi::new(
+            Http::from_env(),
+            base_url,
+            "gpt-test".to_string(),
+            "token".to_string(),
+        );
+        let response = provider.request(&prompt()).await.unwrap();
+        assert_eq!(response.model, "gpt-test");
+        assert_eq!(response.result, "hello openai");
+    }
+
+    #[tokio::test]
+    async fn test_local() {
+        let base_url = mock_server(
+            "/api/chat",
+            json!({
+                "model": "llama-test",
+                "message": {"role": "assistant", "content": "hello ollama"},
+                "done": true
+            }),
+        )
+        .await;
+        let provider = Local::new(
+            Http::from_env(),
+            base_url,
+            "llama-test".to_string(),
+            String::new(),
+            LocalFormat::Ollama,
+        );
+        let response = provider.request(&prompt()).await.unwrap();
+        assert_eq!(response.model, "llama-test");
+        assert_eq!(response.result, "hello ollama");
+    }
+
+    #[test]
+    fn test_generation_overrides() {
+        let overrides = GenerationOverrides {
+            temperature: Some(0.8),
+            max_output_tokens: Some(16384),
+            ..Default::default()
+        };
+        let config = GenerationConfig::default()
+            .with_overrides(&overrides)
+            .unwrap();
+        assert_eq!(config.temperature, 0.8);
+        assert_eq!(config.max_output_tokens, 16384);
+        assert_eq!(config.top_k, 1);
+        assert_eq!(config.overrides.top_p, None);
+
+        let overrides = GenerationOverrides {
+            top_p: Some(1.5),
+            ..Default::default()
+        };
+        assert!(
+            GenerationConfig::default()
+                .with_overrides(&overrides)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_output_limit() {
+        let request = AiRequest::new(
+            "prompt".to_string(),
+            GenerationConfig {
+                max_output_tokens: 32768,
+                ..Default::default()
+            },
+        );
+        let clamped = request
+            .clone()
+            .with_output_limit(max_output_tokens("chatgpt"));
+        assert_eq!(clamped.config.max_output_tokens, 16384);
+        let clamped = request.with_output_limit(max_output_tokens("gemini"));
+        assert_eq!(clamped.config.max_output_tokens, 32768);
+    }
+
+    #[tokio::test]
+    async fn test_unsupported() {
+        let err = request(&Http::from_env(), "unknown", &prompt())
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+    }
+}
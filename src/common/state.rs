@@ -0,0 +1,32 @@
+use std::sync::Arc;
+
+use axum::extract::FromRef;
+
+use crate::common::{database::Database, http::Http, job::JobQueue};
+
+/// アプリケーション全体で共有する状態
+/// 各ハンドラは `State<Arc<Database>>` や `State<Http>` で必要なものだけを取り出す
+#[derive(Clone)]
+pub struct AppState {
+    pub db: Arc<Database>,
+    pub http: Http,
+    pub jobs: Arc<JobQueue>,
+}
+
+impl FromRef<AppState> for Arc<Database> {
+    fn from_ref(state: &AppState) -> Self {
+        state.db.clone()
+    }
+}
+
+impl FromRef<AppState> for Http {
+    fn from_ref(state: &AppState) -> Self {
+        state.http.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<JobQueue> {
+    fn from_ref(state: &AppState) -> Self {
+        state.jobs.clone()
+    }
+}
@@ -0,0 +1,164 @@
+use log::error;
+
+use crate::{
+    common::database::Database,
+    models::template::{
+        BUILTIN_VERSION, PromptTemplate, TemplateInput, TemplateVersion, UserTemplate,
+    },
+};
+
+// プロンプトテンプレートの登録・取得
+// 再デプロイせずに文言を変更できるよう、テンプレートをデータベースで管理する
+
+const TEMPLATES: &str = "prompt_templates";
+const VERSIONS: &str = "prompt_template_versions";
+const USER_TEMPLATES: &str = "user_templates";
+
+pub async fn list(db: &Database) -> Result<Vec<PromptTemplate>, String> {
+    let mut templates = db.read_all::<PromptTemplate>(TEMPLATES, None).await?;
+    templates.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(templates)
+}
+
+pub async fn get(db: &Database, name: &str) -> Result<Option<PromptTemplate>, String> {
+    db.read::<PromptTemplate>(TEMPLATES, name).await
+}
+
+pub async fn version(
+    db: &Database,
+    name: &str,
+    version: u32,
+) -> Result<Option<TemplateVersion>, String> {
+    db.read::<TemplateVersion>(VERSIONS, &TemplateVersion::id(name, version))
+        .await
+}
+
+/// テンプレートの全バージョン (古い順)
+pub async fn versions(db: &Database, name: &str) -> Result<Vec<TemplateVersion>, String> {
+    let mut versions = db
+        .read_by::<TemplateVersion>(VERSIONS, "name", name)
+        .await?;
+    versions.sort_by_key(|v| v.version);
+    Ok(versions)
+}
+
+/// 有効なバージョン
+/// データベースに登録されていない場合は None
+pub async fn active(db: &Database, name: &str) -> Result<Option<TemplateVersion>, String> {
+    match get(db, name).await? {
+        Some(template) => version(db, name, template.active_version).await,
+        None => Ok(None),
+    }
+}
+
+/// 新しいバージョンを作成する
+/// バージョンは作成のみ行い、既存のバージョンは上書きしない
+pub async fn create(
+    db: &Database,
+    name: &str,
+    input: TemplateInput,
+    user_id: &str,
+) -> Result<(PromptTemplate, TemplateVersion), String> {
+    let now = chrono::Utc::now().to_rfc3339();
+    let current = get(db, name).await?;
+    let number = PromptTemplate::next_version(current.as_ref(), &versions(db, name).await?);
+
+    let version = TemplateVersion {
+        name: name.to_string(),
+        version: number,
+        description: input.description,
+        system: input.system,
+        user: input.user,
+        note: input.note,
+        created_by: user_id.to_string(),
+        created_at: now.clone(),
+    };
+    // 同じ番号のバージョンが同時に作成された場合はここで失敗する
+    db.create(
+        VERSIONS,
+        &TemplateVersion::id(name, number),
+        version.clone(),
+    )
+    .await?;
+
+    // ポインタはトランザクション内で読み直して更新し、同時のロールバックを上書きしない
+    let activate = input.activate;
+    let updated = db
+        .update_with(TEMPLATES, name, |current: PromptTemplate| {
+            Some(current.advance(number, activate, user_id, &now))
+        })
+        .await;
+    let template = match updated {
+        Ok(Some(template)) => Ok(template),
+        Ok(None) => {
+            let template = PromptTemplate::new(name).advance(number, activate, user_id, &now);
+            db.create(TEMPLATES, name, template.clone())
+                .await
+                .map(|_| template)
+        }
+        Err(e) => Err(e),
+    };
+    match template {
+        Ok(template) => Ok((template, version)),
+        Err(e) => {
+            error!("Failed to save template pointer: {}", e);
+            Err(e)
+        }
+    }
+}
+
+/// 有効なバージョンを切り替える (ロールバック)
+/// BUILTIN_VERSION (0) は組み込みのテンプレートに戻す
+pub async fn activate(
+    db: &Database,
+    name: &str,
+    number: u32,
+    user_id: &str,
+) -> Result<Option<PromptTemplate>, String> {
+    if number != BUILTIN_VERSION && version(db, name, number).await?.is_none() {
+        return Ok(None);
+    }
+
+    // 同時に作成されたバージョンの latest_version を上書きしないよう、トランザクション内で読み直す
+    db.update_with(TEMPLATES, name, |current: PromptTemplate| {
+        Some(PromptTemplate {
+            active_version: number,
+            updated_by: user_id.to_string(),
+            updated_at: chrono::Utc::now().to_rfc3339(),
+            ..current
+        })
+    })
+    .await
+}
+
+/// ユーザーの非公開テンプレート (名前順)
+pub async fn list_user(db: &Database, user_id: &str) -> Result<Vec<UserTemplate>, String> {
+    let mut templates = db
+        .read_by::<UserTemplate>(USER_TEMPLATES, "user_id", user_id)
+        .await?;
+    templates.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(templates)
+}
+
+pub async fn get_user(
+    db: &Database,
+    user_id: &str,
+    name: &str,
+) -> Result<Option<UserTemplate>, String> {
+    db.read::<UserTemplate>(USER_TEMPLATES, &UserTemplate::id(user_id, name))
+        .await
+}
+
+pub async fn save_user(db: &Database, template: &UserTemplate, is_new: bool) -> Result<(), String> {
+    let id = UserTemplate::id(&template.user_id, &template.name);
+    if is_new {
+        db.create(USER_TEMPLATES, &id, template.clone()).await
+    } else {
+        db.update(USER_TEMPLATES, &id, template.clone()).await
+    }
+}
+
+pub async fn delete_user(db: &Database, user_id: &str, name: &str) -> Result<(), String> {
+    db.delete(USER_TEMPLATES, &UserTemplate::id(user_id, name))
+        .await
+}
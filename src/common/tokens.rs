@@ -0,0 +1,186 @@
+use crate::common::provider::env_or;
+
+// トークン数の見積もりと長い入力の分割
+// トークナイザはプロバイダごとに異なるため、文字種ごとの係数で概算する
+
+/// プロバイダごとの1文字あたりのトークン数 (日本語などの全角文字, その他の文字)
+fn ratio(target_ai: &str) -> (f64, f64) {
+    match target_ai {
+        "gemini" => (1.0, 0.25),
+        "claude" => (1.2, 0.3),
+        "chatgpt" => (1.0, 0.25),
+        // ローカルモデルのトークナイザは日本語の効率が低いものが多い
+        _ => (1.5, 0.3),
+    }
+}
+
+/// トークン数の概算
+pub fn estimate(target_ai: &str, text: &str) -> usize {
+    let (wide, narrow) = ratio(target_ai);
+    let (wide_chars, narrow_chars) = text.chars().fold((0usize, 0usize), |(w, n), c| {
+        if c.is_ascii() { (w, n + 1) } else { (w + 1, n) }
+    });
+    (wide_chars as f64 * wide + narrow_chars as f64 * narrow).ceil() as usize
+}
+
+/// 1回のリクエストに含める文字起こしの上限 (トークン数)
+/// これを超える場合は分割して要約する。MEETING_CHUNK_TOKENS で上書きできる
+pub fn chunk_budget(target_ai: &str) -> usize {
+    let default = match target_ai {
+        "gemini" => 120_000,
+        "claude" => 100_000,
+        "chatgpt" => 60_000,
+        _ => 6_000,
+    };
+    env_or("MEETING_CHUNK_TOKENS", "")
+        .parse::<usize>()
+        .ok()
+        .filter(|v| *v > 0)
+        .unwrap_or(default)
+}
+
+/// 会話の続きで送るこれまでのターンの上限 (トークン数)
+/// 超える場合は古いターンから省く。CONVERSATION_MAX_TOKENS で上書きできる
+pub fn conversation_budget(target_ai: &str) -> usize {
+    let default = match target_ai {
+        "gemini" => 200_000,
+        "claude" => 150_000,
+        "chatgpt" => 100_000,
+        _ => 6_000,
+    };
+    env_or("CONVERSATION_MAX_TOKENS", "")
+        .parse::<usize>()
+        .ok()
+        .filter(|v| *v > 0)
+        .unwrap_or(default)
+}
+
+/// 話者の発言の始まりか
+/// `山田:` `山田 太郎：` `[00:01:02] 山田` `00:01 山田` などの形式
+fn is_speaker_line(line: &str) -> bool {
+    let line = line.trim_start();
+    if line.starts_with('[') || line.chars().next().is_some_and(|c| c.is_ascii_digit()) {
+        return true;
+    }
+    match line.find([':', '：']) {
+        // 話者名は短い
+        Some(i) => i > 0 && line[..i].chars().count() <= 20,
+        None => false,
+    }
+}
+
+/// 文字起こしを話者の発言または段落の単位に分ける
+fn split_units(text: &str) -> Vec<String> {
+    let mut units: Vec<String> = Vec::new();
+    let mut current = String::new();
+    for line in text.lines() {
+        let boundary = line.trim().is_empty() || is_speaker_line(line);
+        if boundary && !current.trim().is_empty() {
+            units.push(std::mem::take(&mut current));
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        if !current.is_empty() {
+            current.push('\n');
+        }
+        current.push_str(line);
+    }
+    if !current.trim().is_empty() {
+        units.push(current);
+    }
+    units
+}
+
+/// 1つの単位が上限を超える場合は文の区切り、それでも超える場合は文字数で分ける
+fn split_long(unit: &str, max_tokens: usize, target_ai: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    for sentence in unit.split_inclusive(['。', '！', '？', '\n']) {
+        if !current.is_empty()
+            && estimate(target_ai, &current) + estimate(target_ai, sentence) > max_tokens
+        {
+            parts.push(std::mem::take(&mut current));
+        }
+        if estimate(target_ai, sentence) > max_tokens {
+            let mut piece = String::new();
+            for c in sentence.chars() {
+                piece.push(c);
+                if estimate(target_ai, &piece) >= max_tokens {
+                    parts.push(std::mem::take(&mut piece));
+                }
+            }
+            current = piece;
+            continue;
+        }
+        current.push_str(sentence);
+    }
+    if !current.is_empty() {
+        parts.push(current);
+    }
+    parts
+}
+
+/// 上限のトークン数に収まるよう、話者の発言・段落の区切りで分割する
+pub fn split(text: &str, max_tokens: usize, target_ai: &str) -> Vec<String> {
+    let max_tokens = max_tokens.max(1);
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    let mut current_tokens = 0;
+
+    for unit in split_units(text) {
+        let pieces = if estimate(target_ai, &unit) > max_tokens {
+            split_long(&unit, max_tokens, target_ai)
+        } else {
+            vec![unit]
+        };
+        for piece in pieces {
+            let tokens = estimate(target_ai, &piece) + 1;
+            if !current.is_empty() && current_tokens + tokens > max_tokens {
+                chunks.push(std::mem::take(&mut current));
+                current_tokens = 0;
+            }
+            if !current.is_empty() {
+                current.push('\n');
+            }
+            current.push_str(&piece);
+            current_tokens += tokens;
+        }
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+#[cfg(test)]
+// 見積もりと分割位置を確認する
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split() {
+        assert_eq!(estimate("gemini", "会議abcd"), 3);
+        assert!(estimate("local", "会議") > estimate("gemini", "会議"));
+
+        let text = "山田: 本日の議題は仕様です。\n続きの発言です。\n佐藤: 了解しました。\n\n[00:10:00] 鈴木 次の議題に移ります。";
+        let chunks = split(text, 25, "gemini");
+        assert_eq!(
+            chunks,
+            vec![
+                "山田: 本日の議題は仕様です。\n続きの発言です。",
+                "佐藤: 了解しました。",
+                "[00:10:00] 鈴木 次の議題に移ります。",
+            ]
+        );
+        // 上限に余裕があれば発言をまとめる
+        assert_eq!(split(text, 1000, "gemini").len(), 1);
+
+        // 1つの発言が上限を超える場合は文で区切る
+        let long = "山田: ".to_string() + &"これは長い発言です。".repeat(10);
+        let chunks = split(&long, 25, "gemini");
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|c| estimate("gemini", c) <= 25));
+        assert_eq!(chunks.concat().replace('\n', ""), long);
+    }
+}
@@ -30,6 +30,14 @@ async fn main() {
     // データベースの初期化
     // Arc は複数のスレッドで共有するためのスマートポインタ
     let db = Arc::new(common::database::Database::new().await);
+    // 上流AIへのHTTPクライアント
+    // コネクションプールを共有するため、起動時に一度だけ生成する
+    let http = common::http::Http::from_env();
+    // 非同期ジョブの実行キュー
+    // 中断されたジョブは起動時に再開する
+    let jobs = Arc::new(common::job::JobQueue::from_env());
+    tokio::spawn(api::job::resume(db.clone(), http.clone(), jobs.clone()));
+    let state = common::state::AppState { db, http, jobs };
 
     let endpoint = Router::new()
         // サーバー時間を返すエンドポイント
@@ -43,13 +51,69 @@ async fn main() {
         // パスワード検証
         // JWT 生成
         .route("/api/public/user/signin", post(api::user::signin))
+        // 利用できるプロンプトタイプの一覧 (組み込み・共有テンプレート)
+        .route("/api/public/ai/prompts", get(api::checker::public_prompts))
         // 認証後のエンドポイント例
         // JWT -> Claims検証
         .route("/api/private/health", get(api::initial::private_health))
+        // 利用できるプロンプトタイプの一覧 (ユーザーの非公開テンプレートを含む)
+        .route("/api/private/ai/prompts", get(api::checker::prompts))
         .route(
             "/api/private/ai/{target_ai}/{prompt_type}",
             post(api::checker::switcher),
         )
+        // Server-Sent Events で部分的な出力を返す
+        .route(
+            "/api/private/ai/{target_ai}/{prompt_type}/stream",
+            post(api::checker::stream_switcher),
+        )
+        // 時間のかかる処理をジョブとして受け付け、結果は後から取得する
+        .route(
+            "/api/private/jobs/{target_ai}/{prompt_type}",
+            post(api::job::create),
+        )
+        .route(
+            "/api/private/jobs/{id}",
+            get(api::job::get).delete(api::job::cancel),
+        )
+        // AIの結果に対する続きの依頼
+        .route(
+            "/api/private/conversations/{id}",
+            get(api::conversation::get).post(api::conversation::reply),
+        )
+        // AIへのリクエストの履歴
+        .route("/api/private/history", get(api::history::list))
+        .route(
+            "/api/private/history/{id}",
+            get(api::history::get).delete(api::history::delete),
+        )
+        // mail の結果のメール文章の変数に値を差し込む
+        .route("/api/private/history/{id}/fill", post(api::history::fill))
+        // 構造化された議事録の ToDo・次回予定を .ics / CSV で書き出す
+        .route("/api/private/meeting/export", post(api::meeting::export))
+        // ユーザーの非公開テンプレート
+        .route("/api/private/templates", get(api::user_template::list))
+        .route(
+            "/api/private/templates/{name}",
+            get(api::user_template::get)
+                .post(api::user_template::create)
+                .put(api::user_template::update)
+                .delete(api::user_template::delete),
+        )
+        // プロンプトテンプレートの管理 (管理者のみ)
+        .route("/api/private/admin/prompts", get(api::template::list))
+        .route(
+            "/api/private/admin/prompts/{name}",
+            get(api::template::get).post(api::template::create),
+        )
+        .route(
+            "/api/private/admin/prompts/{name}/diff",
+            get(api::template::diff),
+        )
+        .route(
+            "/api/private/admin/prompts/{name}/rollback",
+            post(api::template::rollback),
+        )
         .layer(
             CorsLayer::new()
                 .allow_methods([
@@ -66,7 +130,7 @@ async fn main() {
                     "Authorization".parse().unwrap(),
                 ]),
         )
-        .with_state(db);
+        .with_state(state);
 
     // Access-Control-Allow-Origin: *
 
@@ -0,0 +1,297 @@
+use serde::{Deserialize, Serialize};
+
+use crate::common::render;
+
+// 社内チャット (Slack, Teams) 向けのメッセージの調整
+// 調整条件はリクエストボディの `chat` で指定する
+
+/// 候補の数の上限
+pub const MAX_ALTERNATIVES: usize = 5;
+
+/// 文字数の上限の範囲
+const MIN_CHARS: usize = 20;
+const MAX_CHARS: usize = 2000;
+
+/// 候補の項目名と見出しに含まれる語 (推奨順)
+const SECTIONS: [(&str, &[&str]); MAX_ALTERNATIVES] = [
+    ("1", &["候補1", "候補 1", "Option 1"]),
+    ("2", &["候補2", "候補 2", "Option 2"]),
+    ("3", &["候補3", "候補 3", "Option 3"]),
+    ("4", &["候補4", "候補 4", "Option 4"]),
+    ("5", &["候補5", "候補 5", "Option 5"]),
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Channel {
+    #[default]
+    Slack,
+    Teams,
+}
+
+impl Channel {
+    fn instruction(&self) -> &'static str {
+        match self {
+            Channel::Slack => {
+                "Slack。要点を先に書き、短い段落や箇条書きで読みやすくする。強調は *太字* (Slack の mrkdwn) を使う"
+            }
+            Channel::Teams => {
+                "Microsoft Teams。Slack よりやや丁寧に、要点を先に書く。強調は **太字** (markdown) を使う"
+            }
+        }
+    }
+}
+
+/// 相手との関係
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Recipient {
+    // 同僚
+    #[default]
+    Peer,
+    // 後輩・部下
+    Junior,
+    // 上司・先輩
+    Senior,
+    // 役員・他部署の責任者
+    Executive,
+}
+
+impl Recipient {
+    fn instruction(&self) -> &'static str {
+        match self {
+            Recipient::Peer => "同僚。です・ます調を基本に、堅すぎない親しみのある表現",
+            Recipient::Junior => {
+                "後輩・部下。威圧的にならないよう配慮しつつ、依頼や指示を明確にする"
+            }
+            Recipient::Senior => {
+                "上司・先輩。丁寧語を基本に、結論と相手にしてほしいことを簡潔に伝える"
+            }
+            Recipient::Executive => {
+                "役員・他部署の責任者。敬語を使い、結論・判断してほしい事項・期限を冒頭で明確にする"
+            }
+        }
+    }
+}
+
+/// リクエストボディの `chat`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatOptions {
+    #[serde(default)]
+    pub channel: Channel,
+    #[serde(default)]
+    pub recipient: Recipient,
+    // 1つの候補の文字数の上限
+    #[serde(default = "default_max_chars")]
+    pub max_chars: usize,
+    // 候補の数
+    #[serde(default = "default_alternatives")]
+    pub alternatives: usize,
+}
+
+fn default_max_chars() -> usize {
+    200
+}
+
+fn default_alternatives() -> usize {
+    3
+}
+
+impl Default for ChatOptions {
+    fn default() -> Self {
+        ChatOptions {
+            channel: Channel::default(),
+            recipient: Recipient::default(),
+            max_chars: default_max_chars(),
+            alternatives: default_alternatives(),
+        }
+    }
+}
+
+impl ChatOptions {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(MIN_CHARS..=MAX_CHARS).contains(&self.max_chars) {
+            return Err(format!(
+                "max_chars must be between {} and {}",
+                MIN_CHARS, MAX_CHARS
+            ));
+        }
+        if !(1..=MAX_ALTERNATIVES).contains(&self.alternatives) {
+            return Err(format!(
+                "alternatives must be between 1 and {}",
+                MAX_ALTERNATIVES
+            ));
+        }
+        Ok(())
+    }
+
+    /// プロンプトの「調整条件」
+    pub fn to_prompt(&self) -> String {
+        [
+            "## 調整条件".to_string(),
+            format!("*   **チャットツール:** {}", self.channel.instruction()),
+            format!("*   **相手:** {}", self.recipient.instruction()),
+            format!("*   **文字数の上限:** {}文字", self.max_chars),
+            format!("*   **候補の数:** {}", self.alternatives),
+        ]
+        .join("\n")
+    }
+}
+
+/// 書き換えの候補
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Alternative {
+    // 1 から。モデルが推奨する順
+    pub rank: usize,
+    pub text: String,
+    // 候補の特徴・使い分け
+    pub note: String,
+    // 文字数
+    pub length: usize,
+    // 文字数の上限 (`max_chars`) を超えている
+    pub over_limit: bool,
+}
+
+/// チャットメッセージの調整の出力
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChatRewrite {
+    pub alternatives: Vec<Alternative>,
+    // 出力が指定の形式に沿っておらず、候補を取り出せなかった
+    pub fallback: bool,
+}
+
+impl ChatRewrite {
+    /// モデルの出力 (markdown) を候補に分ける
+    /// 候補の見出しがない場合は、最初のコードブロック (なければ出力全体) を1つの候補とする
+    /// 指定した数より多い候補は推奨順に切り詰め、文字数の上限を超える候補には `over_limit` を付ける
+    pub fn parse(output: &str, options: &ChatOptions) -> Self {
+        let mut sections = render::split_sections(output, &SECTIONS);
+        let alternatives: Vec<Alternative> = SECTIONS
+            .iter()
+            .filter_map(|(key, _)| sections.remove(key))
+            .take(options.alternatives)
+            .enumerate()
+            .map(|(i, section)| {
+                let text = render::code_block(&section).unwrap_or(section.clone());
+                let note = render::list_items(&section).join("\n");
+                Alternative::new(i + 1, &text, note, options.max_chars)
+            })
+            .collect();
+        if !alternatives.is_empty() {
+            return ChatRewrite {
+                alternatives,
+                fallback: false,
+            };
+        }
+
+        let text = render::code_block(output).unwrap_or(output.to_string());
+        ChatRewrite {
+            alternatives: vec![Alternative::new(1, &text, String::new(), options.max_chars)],
+            fallback: true,
+        }
+    }
+}
+
+impl Alternative {
+    fn new(rank: usize, text: &str, note: String, max_chars: usize) -> Self {
+        let text = text.trim().to_string();
+        let length = text.chars().count();
+        Alternative {
+            rank,
+            length,
+            over_limit: length > max_chars,
+            text,
+            note,
+        }
+    }
+}
+
+#[cfg(test)]
+// 調整条件の検証と候補の取り出しを確認する
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_options() {
+        let options: ChatOptions =
+            serde_json::from_value(serde_json::json!({"channel": "teams", "recipient": "senior"}))
+                .unwrap();
+        assert_eq!(options.max_chars, 200);
+        assert!(options.validate().is_ok());
+        assert!(options.to_prompt().contains("Microsoft Teams"));
+
+        let options = ChatOptions {
+            alternatives: 6,
+            ..Default::default()
+        };
+        assert!(options.validate().is_err());
+    }
+
+    #[test]
+    fn test_parse() {
+        let output = r#"### 候補1（推奨）
+```
+お疲れさまです。明日の定例、15時開始に変更してもよいでしょうか？
+```
+*   結論を先に書き、確認の形にしています
+
+### 候補2
+```
+明日の定例を15時からに変更したいです。ご都合いかがでしょうか。
+```
+"#;
+        let rewrite = ChatRewrite::parse(output, &ChatOptions::default());
+        assert_eq!(rewrite.alternatives.len(), 2);
+        assert_eq!(rewrite.alternatives[0].rank, 1);
+        assert!(rewrite.alternatives[0].text.starts_with("お疲れさまです。"));
+        assert_eq!(
+            rewrite.alternatives[0].note,
+            "結論を先に書き、確認の形にしています"
+        );
+        assert_eq!(rewrite.alternatives[1].length, 32);
+        assert!(!rewrite.alternatives[1].over_limit);
+        assert!(!rewrite.fallback);
+
+        let rewrite = ChatRewrite::parse(
+            "明日の定例を15時からにできますか？",
+            &ChatOptions::default(),
+        );
+        assert_eq!(
+            rewrite.alternatives[0].text,
+            "明日の定例を15時からにできますか？"
+        );
+        assert!(rewrite.fallback);
+    }
+
+    #[test]
+    fn test_parse_limits() {
+        let output = r#"### 候補1
+```
+明日の定例を15時開始に変更してもよいでしょうか？参加者の皆さんのご都合も確認させてください。
+```
+
+### 候補2
+```
+明日の定例、15時からでも大丈夫ですか？
+```
+
+### 候補3
+```
+明日の定例を15時に変更できますか？
+```
+"#;
+        let options = ChatOptions {
+            max_chars: 20,
+            alternatives: 2,
+            ..Default::default()
+        };
+        let rewrite = ChatRewrite::parse(output, &options);
+        // 指定した数より多い候補は推奨順に切り詰める
+        assert_eq!(rewrite.alternatives.len(), 2);
+        assert_eq!(rewrite.alternatives[1].rank, 2);
+        // 文字数の上限を超える候補は残したうえで示す
+        assert!(rewrite.alternatives[0].over_limit);
+        assert!(rewrite.alternatives[0].length > 20);
+        assert!(!rewrite.alternatives[1].over_limit);
+    }
+}
@@ -86,6 +86,15 @@ impl Claims {
         self.exp > now
     }
 
+    /// 管理者か
+    /// ADMIN_USER_IDS にカンマ区切りで指定したユーザーを管理者とする
+    pub fn is_admin(&self) -> bool {
+        std::env::var("ADMIN_USER_IDS")
+            .unwrap_or_default()
+            .split(',')
+            .any(|id| !id.trim().is_empty() && id.trim() == self.user_id)
+    }
+
     pub fn to_token(&self) -> Result<String, String> {
         encode(&Header::default(), self, &KEYS.encoding).map_err(|e| e.to_string())
     }
@@ -0,0 +1,329 @@
+use std::sync::LazyLock;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::common::{
+    diff::{self, DiffSpan},
+    render::{self, escape},
+};
+
+// 契約書・NDA の条項のリスクレビュー
+// 契約書レビュープロンプトの「出力形式」の番号付きの項目に合わせる
+
+/// 項目名と見出しに含まれる語 (出力の順)
+const SECTIONS: &[(&str, &[&str])] = &[
+    ("summary", &["総合評価", "Summary"]),
+    ("clauses", &["リスク評価", "Clause"]),
+    ("missing", &["不足", "Missing"]),
+    ("redlines", &["修正案", "Redline"]),
+];
+
+/// 条項のリスク評価の行 (`【高】第5条（損害賠償）: 指摘内容`)
+static CLAUSE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^[\[【［(（]\s*([^\]】］)）]+?)\s*[\]】］)）]\s*([^:：]+)(?:[:：]\s*(.*))?$")
+        .unwrap()
+});
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RiskLevel {
+    High,
+    Medium,
+    Low,
+}
+
+impl RiskLevel {
+    fn parse(text: &str) -> Option<Self> {
+        match text.trim().to_lowercase().as_str() {
+            "高" | "high" => Some(RiskLevel::High),
+            "中" | "medium" => Some(RiskLevel::Medium),
+            "低" | "low" => Some(RiskLevel::Low),
+            _ => None,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            RiskLevel::High => "高",
+            RiskLevel::Medium => "中",
+            RiskLevel::Low => "低",
+        }
+    }
+}
+
+/// 条項ごとのリスク評価
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ClauseRisk {
+    pub clause: String,
+    // 形式に沿わない行は None
+    pub level: Option<RiskLevel>,
+    pub comment: String,
+}
+
+impl ClauseRisk {
+    fn parse(item: &str) -> Self {
+        let line = item.replace('\n', " ");
+        match CLAUSE.captures(&line) {
+            Some(c) => ClauseRisk {
+                clause: c[2].trim().to_string(),
+                level: RiskLevel::parse(&c[1]),
+                comment: c.get(3).map_or("", |m| m.as_str()).trim().to_string(),
+            },
+            None => ClauseRisk {
+                clause: String::new(),
+                level: None,
+                comment: line,
+            },
+        }
+    }
+}
+
+/// 不足している標準条項
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MissingClause {
+    pub clause: String,
+    pub reason: String,
+}
+
+impl MissingClause {
+    fn parse(item: &str) -> Self {
+        let (clause, reason) = item.split_once(['：', ':']).unwrap_or((item, ""));
+        MissingClause {
+            clause: clause.trim().to_string(),
+            reason: reason.replace('\n', " ").trim().to_string(),
+        }
+    }
+}
+
+/// 条文の修正案
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Redline {
+    pub clause: String,
+    pub before: String,
+    pub after: String,
+    #[serde(default)]
+    pub reason: Option<String>,
+    // 修正前と修正後の単語単位の差分
+    pub diff: Vec<DiffSpan>,
+}
+
+impl Redline {
+    /// 1行目を条項名、`修正前:`, `修正後:`, `理由:` で始まる行をそれぞれの値とする
+    fn parse(item: &str) -> Self {
+        let mut lines = item.lines();
+        let clause = lines.next().unwrap_or_default().trim().to_string();
+        // 修正前, 修正後, 理由
+        let mut values = [String::new(), String::new(), String::new()];
+        let mut current: Option<usize> = None;
+        for line in lines {
+            let line = line.trim().trim_start_matches('・').trim();
+            let field =
+                line.split_once(['：', ':'])
+                    .and_then(|(label, value)| match label.trim() {
+                        "修正前" | "Before" => Some((0, value)),
+                        "修正後" | "After" => Some((1, value)),
+                        "理由" | "Reason" => Some((2, value)),
+                        _ => None,
+                    });
+            match (field, current) {
+                (Some((i, value)), _) => {
+                    values[i].push_str(value.trim());
+                    current = Some(i);
+                }
+                // 値の続きの行
+                (None, Some(i)) => {
+                    values[i].push('\n');
+                    values[i].push_str(line);
+                }
+                (None, None) => (),
+            }
+        }
+
+        let [before, after, reason] = values;
+        let (before, after) = (unquote(&before), unquote(&after));
+        Redline {
+            clause,
+            diff: diff::diff(&before, &after),
+            before,
+            after,
+            reason: (!reason.is_empty()).then_some(reason),
+        }
+    }
+}
+
+/// 値全体を囲むかぎ括弧・引用符を取り除く
+fn unquote(text: &str) -> String {
+    let text = text.trim();
+    for (open, close) in [("「", "」"), ("『", "』"), ("\"", "\""), ("“", "”")] {
+        if let Some(inner) = text.strip_prefix(open).and_then(|t| t.strip_suffix(close)) {
+            return inner.trim().to_string();
+        }
+    }
+    text.to_string()
+}
+
+/// 契約書レビューの出力
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ContractReview {
+    pub summary: Vec<String>,
+    pub clauses: Vec<ClauseRisk>,
+    pub missing: Vec<MissingClause>,
+    pub redlines: Vec<Redline>,
+    // 上記の項目から作成したレポート (安全なHTML)。修正案は `<ins>`/`<del>` で表す
+    pub report: String,
+    // 出力が指定の形式に沿っておらず、項目が揃っていない
+    pub fallback: bool,
+}
+
+impl ContractReview {
+    /// モデルの出力 (markdown) を項目に分ける
+    pub fn parse(output: &str) -> Self {
+        let mut sections = render::split_sections(output, SECTIONS);
+        let mut list = |key: &str| {
+            sections
+                .remove(key)
+                .map(|text| render::list_items(&text))
+                .unwrap_or_default()
+        };
+        let summary = list("summary");
+        let clauses: Vec<ClauseRisk> = list("clauses")
+            .iter()
+            .map(|item| ClauseRisk::parse(item))
+            .collect();
+        let missing = list("missing")
+            .iter()
+            // 不足がない場合は「なし」と出力させている
+            .filter(|item| !matches!(item.trim(), "なし" | "None"))
+            .map(|item| MissingClause::parse(item))
+            .collect();
+        let redlines = list("redlines")
+            .iter()
+            .map(|item| Redline::parse(item))
+            .collect();
+
+        let mut review = ContractReview {
+            fallback: summary.is_empty() || clauses.iter().all(|c| c.level.is_none()),
+            summary,
+            clauses,
+            missing,
+            redlines,
+            report: String::new(),
+        };
+        review.report = review.to_html();
+        review
+    }
+
+    /// レポートのHTML
+    pub fn to_html(&self) -> String {
+        let text = |s: &str| escape(s).replace('\n', "<br>");
+        let mut html = String::new();
+
+        html.push_str("<h2>総合評価</h2><ul>");
+        for item in &self.summary {
+            html.push_str(&format!("<li>{}</li>", text(item)));
+        }
+        html.push_str("</ul>");
+
+        html.push_str("<h2>条項ごとのリスク評価</h2><table><thead><tr><th>条項</th><th>リスク</th><th>指摘</th></tr></thead><tbody>");
+        for clause in &self.clauses {
+            html.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+                text(&clause.clause),
+                clause.level.map_or("-", |l| l.label()),
+                text(&clause.comment)
+            ));
+        }
+        html.push_str("</tbody></table>");
+
+        if !self.missing.is_empty() {
+            html.push_str("<h2>不足している標準条項</h2><ul>");
+            for missing in &self.missing {
+                html.push_str(&format!(
+                    "<li><strong>{}</strong>: {}</li>",
+                    text(&missing.clause),
+                    text(&missing.reason)
+                ));
+            }
+            html.push_str("</ul>");
+        }
+
+        if !self.redlines.is_empty() {
+            html.push_str("<h2>修正案</h2>");
+            for redline in &self.redlines {
+                html.push_str(&format!(
+                    "<h3>{}</h3><p>{}</p>",
+                    text(&redline.clause),
+                    diff::to_html(&redline.diff).replace('\n', "<br>")
+                ));
+                if let Some(reason) = &redline.reason {
+                    html.push_str(&format!("<p>理由: {}</p>", text(reason)));
+                }
+            }
+        }
+        render::sanitize(&html)
+    }
+}
+
+#[cfg(test)]
+// 条項の評価・修正案の取り出しとレポートを確認する
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let output = r#"1. **総合評価:**
+    *   損害賠償の上限がなく、受託者に不利な契約です
+
+2. **条項ごとのリスク評価:**
+    *   【高】第5条（損害賠償）: 賠償額の上限がない
+    *   [Low] 第8条（秘密保持）: 一般的な内容
+    *   その他の条項は問題ありません
+
+3. **不足している標準条項:**
+    *   反社会的勢力の排除: 取引先の確認に必要
+
+4. **修正案（レッドライン）:**
+    *   第5条（損害賠償）
+        *   修正前: 「乙は甲に生じた一切の損害を賠償する。」
+        *   修正後: 「乙は甲に生じた直接かつ通常の損害を、委託料の総額を上限として賠償する。」
+        *   理由: 賠償額を予測可能にするため
+"#;
+        let review = ContractReview::parse(output);
+        assert_eq!(review.clauses.len(), 3);
+        assert_eq!(review.clauses[0].clause, "第5条（損害賠償）");
+        assert_eq!(review.clauses[0].level, Some(RiskLevel::High));
+        assert_eq!(review.clauses[0].comment, "賠償額の上限がない");
+        assert_eq!(review.clauses[1].level, Some(RiskLevel::Low));
+        assert_eq!(review.clauses[2].level, None);
+        assert_eq!(
+            review.missing,
+            vec![MissingClause {
+                clause: "反社会的勢力の排除".to_string(),
+                reason: "取引先の確認に必要".to_string(),
+            }]
+        );
+
+        let redline = &review.redlines[0];
+        assert_eq!(redline.clause, "第5条（損害賠償）");
+        assert_eq!(redline.before, "乙は甲に生じた一切の損害を賠償する。");
+        assert_eq!(
+            redline.reason.as_deref(),
+            Some("賠償額を予測可能にするため")
+        );
+        assert!(!review.fallback);
+
+        assert!(
+            review
+                .report
+                .contains("<td>第5条（損害賠償）</td><td>高</td>")
+        );
+        assert!(
+            review
+                .report
+                .contains("<del>一切</del><ins>直接かつ通常</ins>")
+        );
+
+        assert!(ContractReview::parse("問題ありません").fallback);
+    }
+}
@@ -0,0 +1,112 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::{
+    common::{
+        provider::{Role, Turn},
+        tokens,
+    },
+    models::chat::ChatOptions,
+};
+
+// AIの結果に対する会話 (「もっと短く」などの続きの依頼)
+// 最初のリクエストと結果、その後のやり取りをすべて保存する
+
+/// 会話
+/// ドキュメントIDは id
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Conversation {
+    pub id: String,
+    pub user_id: String,
+    pub target_ai: String,
+    pub prompt_type: String,
+    pub template_version: u32,
+    // 最初のリクエストの役割・指示。続きのリクエストでも同じものを使う
+    #[serde(default)]
+    pub system: Option<String>,
+    // 構造化データ (JSON) で出力させる会話か
+    #[serde(default)]
+    pub structured: bool,
+    // 最初のリクエストのチャットの調整条件。続きの結果の候補も同じ条件で取り出す
+    #[serde(default)]
+    pub chat: Option<ChatOptions>,
+    // user と model が交互に並ぶ
+    pub turns: Vec<Turn>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl Conversation {
+    /// レスポンス用。役割・指示は返さない
+    pub fn to_thread(&self) -> Value {
+        json!({
+            "id": self.id,
+            "target_ai": self.target_ai,
+            "prompt_type": self.prompt_type,
+            "template_version": self.template_version,
+            "turns": self.turns,
+            "created_at": self.created_at,
+            "updated_at": self.updated_at,
+        })
+    }
+
+    /// 続きのリクエストで送るこれまでのターン
+    /// 上限のトークン数を超える場合は、古いものから user と model の組で省く
+    /// 戻り値は (送るターン, 省いたターンの数)
+    pub fn context(&self, message: &str, budget: usize) -> (Vec<Turn>, usize) {
+        let estimate = |text: &str| tokens::estimate(&self.target_ai, text);
+        let mut used = estimate(self.system.as_deref().unwrap_or_default()) + estimate(message);
+
+        // 先頭は必ず user になるよう、組の単位で新しい方から数える
+        let mut start = self.turns.len();
+        for pair in self.turns.rchunks(2) {
+            let tokens: usize = pair.iter().map(|t| estimate(&t.text)).sum();
+            if used + tokens > budget || pair[0].role != Role::User {
+                break;
+            }
+            used += tokens;
+            start -= pair.len();
+        }
+        (self.turns[start..].to_vec(), start)
+    }
+}
+
+#[cfg(test)]
+// 古いターンの省き方を確認する
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_context() {
+        let conversation = Conversation {
+            id: "1".to_string(),
+            user_id: "user".to_string(),
+            target_ai: "gemini".to_string(),
+            prompt_type: "mail".to_string(),
+            template_version: 0,
+            system: Some("指示".to_string()),
+            structured: false,
+            chat: None,
+            turns: vec![
+                Turn::user(&"元のメール".repeat(10)),
+                Turn::model("添削結果"),
+                Turn::user("もっと短く"),
+                Turn::model("短い結果"),
+            ],
+            created_at: String::new(),
+            updated_at: String::new(),
+        };
+
+        let (turns, omitted) = conversation.context("敬語に", 1000);
+        assert_eq!((turns.len(), omitted), (4, 0));
+
+        // 上限を超える場合は最初の組から省く
+        let (turns, omitted) = conversation.context("敬語に", 20);
+        assert_eq!(omitted, 2);
+        assert_eq!(turns[0].text, "もっと短く");
+        assert_eq!(turns[0].role, Role::User);
+
+        let (turns, omitted) = conversation.context("敬語に", 1);
+        assert_eq!((turns.len(), omitted), (0, 4));
+    }
+}
@@ -0,0 +1,156 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::common::provider::{Role, Turn};
+
+// Gemini generateContent API のリクエスト・レスポンス
+// https://ai.google.dev/api/generate-content
+
+/// generateContent / streamGenerateContent のリクエストボディ
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GenerateContentRequest {
+    // 役割・指示 (ユーザーの入力とは分けて渡す)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_instruction: Option<Content>,
+    pub contents: Vec<Content>,
+    pub generation_config: GenerationConfig,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Content {
+    // user / model
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+    #[serde(default)]
+    pub parts: Vec<Part>,
+}
+
+impl Content {
+    /// 会話のターン (ロールは user / model)
+    pub fn from_turn(turn: &Turn) -> Self {
+        let role = match turn.role {
+            Role::User => "user",
+            Role::Model => "model",
+        };
+        Content {
+            role: Some(role.to_string()),
+            parts: vec![Part {
+                text: Some(turn.text.clone()),
+            }],
+        }
+    }
+
+    pub fn system(text: &str) -> Self {
+        Content {
+            role: None,
+            parts: vec![Part {
+                text: Some(text.to_string()),
+            }],
+        }
+    }
+
+    /// 全ての part の text を連結する
+    pub fn text(&self) -> String {
+        self.parts
+            .iter()
+            .filter_map(|part| part.text.as_deref())
+            .collect::<Vec<&str>>()
+            .join("")
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Part {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GenerationConfig {
+    pub temperature: f32,
+    pub max_output_tokens: u32,
+    pub top_k: u32,
+    pub top_p: f32,
+    // application/json を指定すると responseSchema に沿った JSON を返す
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_mime_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_schema: Option<Value>,
+}
+
+/// generateContent のレスポンス
+/// streamGenerateContent では同じ形のチャンクが複数届く
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GenerateContentResponse {
+    #[serde(default)]
+    pub candidates: Vec<Candidate>,
+    #[serde(default)]
+    pub prompt_feedback: Option<PromptFeedback>,
+    #[serde(default)]
+    pub usage_metadata: Option<UsageMetadata>,
+    #[serde(default)]
+    pub model_version: Option<String>,
+}
+
+impl GenerateContentResponse {
+    /// 最初の候補のテキスト
+    pub fn text(&self) -> String {
+        self.candidates
+            .first()
+            .and_then(|candidate| candidate.content.as_ref())
+            .map(|content| content.text())
+            .unwrap_or_default()
+    }
+
+    /// 最初の候補の終了理由
+    pub fn finish_reason(&self) -> Option<String> {
+        self.candidates
+            .first()
+            .and_then(|candidate| candidate.finish_reason.clone())
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Candidate {
+    #[serde(default)]
+    pub content: Option<Content>,
+    // STOP, MAX_TOKENS, SAFETY, RECITATION など
+    #[serde(default)]
+    pub finish_reason: Option<String>,
+    #[serde(default)]
+    pub safety_ratings: Vec<SafetyRating>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SafetyRating {
+    pub category: String,
+    pub probability: String,
+    #[serde(default)]
+    pub blocked: bool,
+}
+
+/// プロンプト自体がブロックされた場合に返される
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PromptFeedback {
+    #[serde(default)]
+    pub block_reason: Option<String>,
+    #[serde(default)]
+    pub safety_ratings: Vec<SafetyRating>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageMetadata {
+    #[serde(default)]
+    pub prompt_token_count: u32,
+    #[serde(default)]
+    pub candidates_token_count: u32,
+    #[serde(default)]
+    pub total_token_count: u32,
+}
@@ -0,0 +1,159 @@
+use chrono::{FixedOffset, NaiveDate, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::common::provider::Usage;
+
+// AIへのリクエストの履歴
+// ページを再読み込みしても結果を参照できるよう、リクエストをユーザーごとに保存する
+// 失敗したリクエストもエラーの種類とともに保存する
+
+/// 1ページあたりの件数の既定値と上限
+const PER_PAGE: usize = 20;
+const MAX_PER_PAGE: usize = 100;
+
+/// 履歴
+/// ドキュメントIDは id
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct History {
+    pub id: String,
+    pub user_id: String,
+    // target_ai
+    pub provider: String,
+    // 実際に応答したモデル名
+    pub model: String,
+    pub prompt_type: String,
+    pub template_version: u32,
+    // ユーザーの入力 (message)
+    pub input: String,
+    // モデルの出力 (markdown)
+    pub output: String,
+    // 秒
+    pub elapsed: u64,
+    #[serde(default)]
+    pub usage: Option<Usage>,
+    // 失敗したリクエストのエラー。成功時は None
+    #[serde(default)]
+    pub error: Option<HistoryError>,
+    pub created_at: String,
+}
+
+/// 失敗したリクエストのエラー
+/// code は `switcher` のエラー時の message と同じ (safety, max_tokens など)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryError {
+    pub code: String,
+    pub message: String,
+}
+
+/// 履歴の検索条件
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct HistoryQuery {
+    pub prompt_type: Option<String>,
+    // 作成日 (日本時間) の範囲。両端を含む
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    // 入力・出力に含まれる文字列 (大文字・小文字を区別しない)
+    pub q: Option<String>,
+    // 前のページの `next_cursor`。省略した場合は最初のページ
+    pub cursor: Option<String>,
+    pub per_page: Option<usize>,
+}
+
+impl HistoryQuery {
+    pub fn per_page(&self) -> usize {
+        self.per_page.unwrap_or(PER_PAGE).clamp(1, MAX_PER_PAGE)
+    }
+
+    /// 作成日時 (`created_at`) の範囲 (以上, 未満)
+    /// `created_at` は UTC の RFC 3339 形式で保存しているため、文字列の大小で比較できる
+    pub fn created_at_range(&self) -> (Option<String>, Option<String>) {
+        // 日本時間の日付の開始時刻
+        let start = |date: NaiveDate| {
+            let jst = FixedOffset::east_opt(9 * 3600)?;
+            let start = jst
+                .from_local_datetime(&date.and_hms_opt(0, 0, 0)?)
+                .single()?;
+            Some(start.with_timezone(&Utc).to_rfc3339())
+        };
+        (
+            self.from.and_then(start),
+            self.to.and_then(|to| to.succ_opt()).and_then(start),
+        )
+    }
+
+    /// 入力・出力の文字列の条件
+    /// その他の条件はデータベースのクエリで絞り込む
+    pub fn matches(&self, history: &History) -> bool {
+        match self.q.as_deref().map(str::trim) {
+            Some(q) if !q.is_empty() => {
+                let q = q.to_lowercase();
+                history.input.to_lowercase().contains(&q)
+                    || history.output.to_lowercase().contains(&q)
+            }
+            _ => true,
+        }
+    }
+}
+
+#[cfg(test)]
+// 検索条件を確認する
+mod tests {
+    use chrono::Timelike;
+
+    use super::*;
+
+    fn history(id: &str, prompt_type: &str, output: &str, created_at: &str) -> History {
+        History {
+            id: id.to_string(),
+            user_id: "user".to_string(),
+            provider: "gemini".to_string(),
+            model: "gemini-2.5-pro".to_string(),
+            prompt_type: prompt_type.to_string(),
+            template_version: 0,
+            input: "入力".to_string(),
+            output: output.to_string(),
+            elapsed: 1,
+            usage: None,
+            error: None,
+            created_at: created_at.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_query() {
+        let query = HistoryQuery {
+            from: NaiveDate::from_ymd_opt(2025, 4, 2),
+            to: NaiveDate::from_ymd_opt(2025, 4, 2),
+            ..Default::default()
+        };
+        let (from, to) = query.created_at_range();
+        // 日本時間の 4月2日 0時から 4月3日 0時まで
+        assert_eq!(from.as_deref(), Some("2025-04-01T15:00:00+00:00"));
+        assert_eq!(to.as_deref(), Some("2025-04-02T15:00:00+00:00"));
+        // 秒未満を含む作成日時も文字列の大小で比較できる
+        let created_at = Utc
+            .with_ymd_and_hms(2025, 4, 1, 15, 0, 0)
+            .unwrap()
+            .with_nanosecond(123_456_789)
+            .unwrap()
+            .to_rfc3339();
+        assert!(created_at.as_str() >= from.unwrap().as_str());
+        assert!(created_at.as_str() < to.unwrap().as_str());
+        assert_eq!(HistoryQuery::default().created_at_range(), (None, None));
+
+        let query = HistoryQuery {
+            q: Some("customer".to_string()),
+            ..Default::default()
+        };
+        assert!(query.matches(&history("1", "mail", "Dear Customer", &created_at)));
+        assert!(!query.matches(&history("2", "mail", "ご確認ください", &created_at)));
+        assert_eq!(
+            HistoryQuery {
+                per_page: Some(1000),
+                ..Default::default()
+            }
+            .per_page(),
+            100
+        );
+    }
+}
@@ -0,0 +1,90 @@
+use serde::{Deserialize, Serialize};
+
+use crate::common::render;
+
+// 整合性チェックの出力
+// 整合性チェックプロンプトの「出力形式」の番号付きの項目に合わせる
+
+/// 項目名と見出しに含まれる語 (出力の順)
+const SECTIONS: &[(&str, &[&str])] = &[
+    ("revised_body", &["修正案を反映した本文", "Revised"]),
+    ("summary", &["監査サマリー", "Summary"]),
+    ("report", &["詳細監査レポート", "Report"]),
+    ("remarks", &["監査者所見", "Remarks"]),
+];
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct IntegrityAudit {
+    // 修正案を反映した全文 (markdown)。見出しがない場合は空
+    pub revised_body: String,
+    pub summary: Vec<String>,
+    // フェーズごとの指摘事項
+    pub report: Vec<String>,
+    pub remarks: Vec<String>,
+    // 出力が指定の形式に沿っておらず、項目が揃っていない
+    pub fallback: bool,
+}
+
+impl IntegrityAudit {
+    /// モデルの出力 (markdown) を項目に分ける
+    pub fn parse(output: &str) -> Self {
+        let mut sections = render::split_sections(output, SECTIONS);
+        // 本文はコードブロックで出力される場合もある
+        let revised_body = sections
+            .remove("revised_body")
+            .map(|text| render::code_block(&text).unwrap_or(text))
+            .unwrap_or_default();
+        let mut list = |key: &str| {
+            sections
+                .remove(key)
+                .map(|text| render::list_items(&text))
+                .unwrap_or_default()
+        };
+        let summary = list("summary");
+        let report = list("report");
+        let remarks = list("remarks");
+        let fallback = revised_body.trim().is_empty() || summary.is_empty() || report.is_empty();
+
+        IntegrityAudit {
+            revised_body: revised_body.trim().to_string(),
+            summary,
+            report,
+            remarks,
+            fallback,
+        }
+    }
+}
+
+#[cfg(test)]
+// 本文とレポートの取り出しを確認する
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let output = r#"## 1. 修正案を反映した本文
+
+本サービスは**2025年4月**に提供を開始します。
+
+## 2. 監査サマリー
+*   **総合評価:** 改善の余地が大きい
+
+## 3. 詳細監査レポート
+*   **(フェーズ1) 全体構造と戦略的整合性:**
+    *   目的が明記されていない
+*   **(フェーズ2) セクション/段落レベルでの論理・意味的整合性:**
+    *   なし
+"#;
+        let audit = IntegrityAudit::parse(output);
+        assert_eq!(
+            audit.revised_body,
+            "本サービスは**2025年4月**に提供を開始します。"
+        );
+        assert_eq!(audit.summary, vec!["総合評価: 改善の余地が大きい"]);
+        assert_eq!(audit.report.len(), 2);
+        assert!(audit.remarks.is_empty());
+        assert!(!audit.fallback);
+
+        assert!(IntegrityAudit::parse("問題ありません").fallback);
+    }
+}
@@ -0,0 +1,146 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+// 非同期ジョブ
+// 時間のかかるAIの処理を受け付けてすぐに返し、結果は後から取得する
+
+/// ジョブの状態
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    // 実行待ち
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobStatus::Queued => "queued",
+            JobStatus::Running => "running",
+            JobStatus::Succeeded => "succeeded",
+            JobStatus::Failed => "failed",
+            JobStatus::Cancelled => "cancelled",
+        }
+    }
+
+    /// 終了した (これ以上状態が変わらない) か
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self,
+            JobStatus::Succeeded | JobStatus::Failed | JobStatus::Cancelled
+        )
+    }
+}
+
+/// 長い文字起こしを分割して要約する場合の処理状況
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobProgress {
+    pub stage: String,
+    pub done: usize,
+    pub total: usize,
+}
+
+/// 失敗したジョブのエラー
+/// code は `switcher` のエラー時の message と同じ (safety, max_tokens など)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobError {
+    pub code: String,
+    pub message: String,
+}
+
+/// ジョブ
+/// ドキュメントIDは id
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Job {
+    pub id: String,
+    pub user_id: String,
+    pub target_ai: String,
+    pub prompt_type: String,
+    // `switcher` と同じリクエストボディ
+    pub body: Value,
+    pub status: JobStatus,
+    // 実行中の部分テキスト (Gemini のみ)
+    #[serde(default)]
+    pub partial: String,
+    #[serde(default)]
+    pub progress: Option<JobProgress>,
+    // 成功時は `switcher` の data と同じ
+    #[serde(default)]
+    pub result: Option<Value>,
+    #[serde(default)]
+    pub error: Option<JobError>,
+    pub created_at: String,
+    // 実行中は定期的に更新する。再起動時に中断されたジョブを判別する
+    pub updated_at: String,
+}
+
+impl Job {
+    pub fn new(user_id: &str, target_ai: &str, prompt_type: &str, body: Value) -> Self {
+        let now = chrono::Utc::now().to_rfc3339();
+        Job {
+            id: uuid::Uuid::new_v4().to_string(),
+            user_id: user_id.to_string(),
+            target_ai: target_ai.to_string(),
+            prompt_type: prompt_type.to_string(),
+            body,
+            status: JobStatus::Queued,
+            partial: String::new(),
+            progress: None,
+            result: None,
+            error: None,
+            created_at: now.clone(),
+            updated_at: now,
+        }
+    }
+
+    /// 最後の更新から `seconds` 秒以上経過しているか
+    pub fn is_stale(&self, seconds: i64) -> bool {
+        match chrono::DateTime::parse_from_rfc3339(&self.updated_at) {
+            Ok(updated_at) => (chrono::Utc::now() - updated_at.to_utc()).num_seconds() >= seconds,
+            Err(_) => true,
+        }
+    }
+
+    /// 状況の確認用。リクエストボディは返さない
+    pub fn to_status(&self) -> Value {
+        serde_json::json!({
+            "id": self.id,
+            "target_ai": self.target_ai,
+            "prompt_type": self.prompt_type,
+            "status": self.status,
+            "partial": self.partial,
+            "progress": self.progress,
+            "result": self.result,
+            "error": self.error,
+            "created_at": self.created_at,
+            "updated_at": self.updated_at,
+        })
+    }
+}
+
+#[cfg(test)]
+// 状態の表記と中断の判定を確認する
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_job() {
+        let mut job = Job::new("user", "gemini", "integrity", serde_json::json!({}));
+        assert_eq!(job.to_status()["status"], "queued");
+        assert_eq!(
+            serde_json::to_value(JobStatus::Cancelled).unwrap(),
+            JobStatus::Cancelled.as_str()
+        );
+        assert!(!job.status.is_finished());
+        assert!(!job.is_stale(300));
+
+        job.updated_at = (chrono::Utc::now() - chrono::Duration::seconds(301)).to_rfc3339();
+        assert!(job.is_stale(300));
+        // リクエストボディは返さない
+        assert!(job.to_status().get("body").is_none());
+    }
+}
@@ -0,0 +1,300 @@
+use std::{collections::HashMap, sync::LazyLock};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    common::render,
+    models::template::{fill, variable_occurrences},
+};
+
+// メールリファクタリングの出力
+// メールプロンプトの「出力形式」の番号付きの項目に合わせる
+
+/// 項目名と見出しに含まれる語 (出力の順)
+const SECTIONS: &[(&str, &[&str])] = &[
+    ("refactored_body", &["リファクタリング後", "Refactored"]),
+    ("inferred_context", &["背景情報", "Inferred Context"]),
+    ("points", &["ポイント", "Points"]),
+    ("reasons", &["理由", "Reasons"]),
+    ("suggestions", &["改善提案", "補足", "Suggestions"]),
+];
+
+/// 文頭の変数の一覧の行 (`{{変数名}}: 説明`)
+static DECLARATION: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^\s*(?:[-*・]\s*)?\{\{\s*([\p{L}\p{N}_]+)\s*\}\}\s*[:：=＝]\s*(.*)$").unwrap()
+});
+
+/// 変数の一覧の見出しとして読み飛ばす行の文字数の上限
+const LABEL_MAX_CHARS: usize = 20;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MailRefactoring {
+    // リファクタリング後のメール文章 (コードブロックの中身)
+    pub refactored_body: String,
+    pub inferred_context: Vec<String>,
+    pub points: Vec<String>,
+    pub reasons: Vec<String>,
+    pub suggestions: Vec<String>,
+    // 文頭の一覧と本文中の `{{変数名}}`
+    pub placeholder_variables: Vec<Placeholder>,
+    // 出力が指定の形式に沿っておらず、項目が揃っていない
+    pub fallback: bool,
+}
+
+impl MailRefactoring {
+    /// モデルの出力 (markdown) を項目に分ける
+    /// メール文章の見出しがない場合は、最初のコードブロック (なければ出力全体) をメール文章とする
+    pub fn parse(output: &str) -> Self {
+        let mut sections = render::split_sections(output, SECTIONS);
+        let mut list = |key: &str| {
+            sections
+                .remove(key)
+                .map(|text| render::list_items(&text))
+                .unwrap_or_default()
+        };
+        let inferred_context = list("inferred_context");
+        let points = list("points");
+        let reasons = list("reasons");
+        let suggestions = list("suggestions");
+        let fallback = !sections.contains_key("refactored_body")
+            || [&inferred_context, &points, &reasons, &suggestions]
+                .iter()
+                .any(|items| items.is_empty());
+
+        let refactored_body = match sections.get("refactored_body") {
+            Some(text) => render::code_block(text).unwrap_or(text.clone()),
+            None => render::code_block(output).unwrap_or(output.to_string()),
+        };
+        let refactored_body = refactored_body.trim().to_string();
+
+        MailRefactoring {
+            placeholder_variables: placeholders(&refactored_body),
+            refactored_body,
+            inferred_context,
+            points,
+            reasons,
+            suggestions,
+            fallback,
+        }
+    }
+}
+
+/// メール文章の変数
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Placeholder {
+    pub name: String,
+    // 文頭の一覧に書かれた説明。一覧にない場合は None
+    pub description: Option<String>,
+    // 本文 (文頭の一覧を除く) での出現回数
+    pub occurrences: usize,
+}
+
+/// メール文章を、文頭の変数の一覧 (変数名, 説明) と本文に分ける
+/// 一覧の前の「【変数】」などの短い見出しと、一覧の後の区切り線は本文に含めない
+/// 一覧がない場合は全体を本文とする
+pub fn split_declarations(text: &str) -> (Vec<(String, String)>, &str) {
+    let mut declarations = Vec::new();
+    let mut offset = 0;
+    let mut body_start = None;
+    for line in text.split_inclusive('\n') {
+        let trimmed = line.trim();
+        if let Some(c) = DECLARATION.captures(trimmed) {
+            declarations.push((c[1].to_string(), c[2].trim().to_string()));
+        } else if trimmed.is_empty()
+            || (!declarations.is_empty() && trimmed.chars().all(|c| "-=─—_*".contains(c)))
+        {
+            // 空行と一覧の後の区切り線は読み飛ばす
+        } else if declarations.is_empty()
+            && trimmed.contains("変数")
+            && trimmed.chars().count() <= LABEL_MAX_CHARS
+        {
+            // 一覧の見出し
+        } else {
+            body_start = Some(offset);
+            break;
+        }
+        offset += line.len();
+    }
+
+    if declarations.is_empty() {
+        return (declarations, text);
+    }
+    (declarations, &text[body_start.unwrap_or(text.len())..])
+}
+
+/// メール文章の変数 (文頭の一覧の順、続いて本文のみに現れるものを出現順に並べる)
+pub fn placeholders(text: &str) -> Vec<Placeholder> {
+    let (declarations, body) = split_declarations(text);
+    let used = variable_occurrences(body);
+    let count = |name: &str| used.iter().filter(|n| *n == name).count();
+
+    let mut placeholders: Vec<Placeholder> = Vec::new();
+    for (name, description) in declarations {
+        if placeholders.iter().any(|p| p.name == name) {
+            continue;
+        }
+        placeholders.push(Placeholder {
+            occurrences: count(&name),
+            description: (!description.is_empty()).then_some(description),
+            name,
+        });
+    }
+    for name in &used {
+        if !placeholders.iter().any(|p| &p.name == name) {
+            placeholders.push(Placeholder {
+                name: name.clone(),
+                description: None,
+                occurrences: count(name),
+            });
+        }
+    }
+    placeholders
+}
+
+/// 変数に値を差し込んだメール文章
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FilledMail {
+    // 文頭の変数の一覧を除いた本文
+    pub body: String,
+    pub placeholders: Vec<Placeholder>,
+    // 値が指定されず本文に残った変数
+    pub unfilled: Vec<String>,
+    // メール文章にない変数の値
+    pub unused: Vec<String>,
+}
+
+/// 文頭の変数の一覧を取り除き、本文の変数に値を差し込む
+/// 空の値は指定されていないものとして扱う
+pub fn fill_placeholders(text: &str, values: &HashMap<String, String>) -> FilledMail {
+    let values: HashMap<String, String> = values
+        .iter()
+        .filter(|(_, v)| !v.trim().is_empty())
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect();
+    let placeholders = placeholders(text);
+    let (_, body) = split_declarations(text);
+
+    let unfilled = placeholders
+        .iter()
+        .filter(|p| p.occurrences > 0 && !values.contains_key(&p.name))
+        .map(|p| p.name.clone())
+        .collect();
+    let mut unused: Vec<String> = values
+        .keys()
+        .filter(|k| !placeholders.iter().any(|p| &p.name == *k))
+        .cloned()
+        .collect();
+    unused.sort();
+
+    FilledMail {
+        body: fill(body, &values).trim().to_string(),
+        placeholders,
+        unfilled,
+        unused,
+    }
+}
+
+#[cfg(test)]
+// 見出しの揺れと形式に沿わない出力の扱いを確認する
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let output = r#"1.  **リファクタリング後のメール文章:**
+
+    ```
+    {{会社名}}: クライアントの会社名
+    {{担当者名}}: ご担当者様の氏名
+
+    件名：{{案件名}}のお打ち合わせ日程について
+
+    {{会社名}}
+    {{担当者名}}様
+
+    1. 候補日を記載しました
+    ```
+
+2. **出力時に推測した背景情報（Inferred Context）:**
+    *   **メールの主な目的:** 日程調整
+    *   **想定されるべきトーン:** 標準的ビジネス
+
+### 3. リファクタリングのポイント
+*   件名を具体化しました
+*   **理由:** の記載は見出しではありません
+
+4. **リファクタリングの理由:** 件名から用件が分かるようにするため
+
+5.  **更なる改善提案・補足点:**
+    *   候補日は3つ程度を提示すると親切です
+        *   曜日も併記する
+"#;
+        let mail = MailRefactoring::parse(output);
+        assert!(
+            mail.refactored_body
+                .starts_with("{{会社名}}: クライアントの会社名")
+        );
+        assert!(mail.refactored_body.ends_with("1. 候補日を記載しました"));
+        assert_eq!(
+            mail.inferred_context,
+            vec![
+                "メールの主な目的: 日程調整",
+                "想定されるべきトーン: 標準的ビジネス"
+            ]
+        );
+        assert_eq!(mail.points.len(), 2);
+        assert_eq!(mail.reasons, vec!["件名から用件が分かるようにするため"]);
+        assert_eq!(
+            mail.suggestions,
+            vec!["候補日は3つ程度を提示すると親切です\n・曜日も併記する"]
+        );
+        assert_eq!(
+            mail.placeholder_variables
+                .iter()
+                .map(|p| p.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["会社名", "担当者名", "案件名"]
+        );
+        assert_eq!(
+            mail.placeholder_variables[0].description.as_deref(),
+            Some("クライアントの会社名")
+        );
+        assert!(!mail.fallback);
+
+        // 見出しがない場合はコードブロックをメール文章とする
+        let mail = MailRefactoring::parse("修正しました。\n\n```\nお世話になっております。\n```");
+        assert_eq!(mail.refactored_body, "お世話になっております。");
+        assert!(mail.fallback);
+
+        let mail = MailRefactoring::parse("お世話になっております。");
+        assert_eq!(mail.refactored_body, "お世話になっております。");
+        assert!(mail.points.is_empty());
+    }
+
+    #[test]
+    fn test_fill_placeholders() {
+        let text = "【変数】\n{{会社名}}: 会社名\n{{担当者名}}：氏名\n{{予備}}: 未使用\n---\n\n{{会社名}}\n{{担当者名}}様\n\n{{会社名}}の皆様には{{日付}}に伺います。";
+        let values = HashMap::from([
+            ("会社名".to_string(), "株式会社サンプル".to_string()),
+            ("担当者名".to_string(), String::new()),
+            ("部署名".to_string(), "営業部".to_string()),
+        ]);
+        let filled = fill_placeholders(text, &values);
+        assert_eq!(
+            filled.body,
+            "株式会社サンプル\n{{担当者名}}様\n\n株式会社サンプルの皆様には{{日付}}に伺います。"
+        );
+        // 空の値は指定されていないものとして扱い、本文にない変数は残っていても報告しない
+        assert_eq!(filled.unfilled, vec!["担当者名", "日付"]);
+        assert_eq!(filled.unused, vec!["部署名"]);
+        assert_eq!(filled.placeholders[0].occurrences, 2);
+        assert_eq!(filled.placeholders[2].occurrences, 0);
+        assert_eq!(filled.placeholders[3].description, None);
+
+        // 一覧がない場合は全体を本文とする
+        let (declarations, body) = split_declarations("{{会社名}}\n{{担当者名}}様");
+        assert!(declarations.is_empty());
+        assert_eq!(body, "{{会社名}}\n{{担当者名}}様");
+    }
+}
@@ -0,0 +1,491 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::common::render::fence;
+
+// 議事録の構造化データ
+// キーは議事録プロンプトの「構造化ルール」に合わせる
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MeetingMinutes {
+    #[serde(rename = "タイトル")]
+    pub title: String,
+    #[serde(rename = "日時", default)]
+    pub date: Option<String>,
+    #[serde(rename = "場所", default)]
+    pub place: Option<String>,
+    #[serde(rename = "要約")]
+    pub summary: String,
+    #[serde(rename = "目的", default)]
+    pub purpose: Option<String>,
+    #[serde(rename = "参加者", default)]
+    pub participants: Vec<String>,
+    #[serde(rename = "議題", default)]
+    pub agenda: Vec<String>,
+    #[serde(rename = "決定事項", default)]
+    pub decisions: Vec<Decision>,
+    #[serde(rename = "ToDoリスト(アクションアイテム)", default)]
+    pub todos: Vec<Todo>,
+    #[serde(rename = "合意事項", default)]
+    pub agreements: Vec<Agreement>,
+    #[serde(rename = "共有事項・報告事項", default)]
+    pub reports: Vec<Report>,
+    #[serde(rename = "提起された課題・懸念点", default)]
+    pub issues: Vec<Issue>,
+    #[serde(rename = "その他特筆事項", default)]
+    pub notes: Option<String>,
+    #[serde(rename = "次回予定", default)]
+    pub next_meeting: Option<NextMeeting>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Decision {
+    #[serde(rename = "内容")]
+    pub content: String,
+    #[serde(rename = "決定背景・理由", default)]
+    pub reason: Option<String>,
+    #[serde(rename = "関連議題", default)]
+    pub agenda: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Todo {
+    #[serde(rename = "担当者", default)]
+    pub assignee: Option<String>,
+    #[serde(rename = "タスク内容")]
+    pub task: String,
+    #[serde(rename = "期限", default)]
+    pub due: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Agreement {
+    #[serde(rename = "内容")]
+    pub content: String,
+    #[serde(rename = "合意形成プロセス", default)]
+    pub process: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Report {
+    #[serde(rename = "発言者", default)]
+    pub speaker: Option<String>,
+    #[serde(rename = "内容")]
+    pub content: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Issue {
+    #[serde(rename = "提起者", default)]
+    pub raised_by: Option<String>,
+    #[serde(rename = "内容")]
+    pub content: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NextMeeting {
+    #[serde(rename = "日時", default)]
+    pub date: Option<String>,
+    #[serde(rename = "議題", default)]
+    pub agenda: Option<String>,
+}
+
+/// ユーザーが事前に把握しているミーティング情報
+/// 議事録プロンプトの「1. ミーティング情報」に差し込む
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MeetingInfo {
+    #[serde(alias = "会議名", default)]
+    pub title: Option<String>,
+    #[serde(alias = "日時", default)]
+    pub date: Option<String>,
+    #[serde(alias = "場所", default)]
+    pub place: Option<String>,
+    // 役職や役割も併記できる (例: 山田 太郎 (プロジェクトマネージャー))
+    #[serde(alias = "参加者リスト", default)]
+    pub participants: Vec<String>,
+    #[serde(alias = "目的", default)]
+    pub purpose: Option<String>,
+    #[serde(alias = "議題", default)]
+    pub agenda: Vec<String>,
+    // 期待する議事録の形式・詳細度
+    #[serde(alias = "期待する形式", default)]
+    pub format: Option<String>,
+    #[serde(alias = "その他特記事項", default)]
+    pub notes: Option<String>,
+}
+
+impl MeetingInfo {
+    /// プロンプトの「1. ミーティング情報」
+    /// 値は指示として扱われないよう、項目ごとにコードブロックで囲む
+    pub fn to_prompt(&self) -> String {
+        let mut md = vec!["## 1. ミーティング情報".to_string()];
+        let mut push = |label: &str, value: String| {
+            md.push(format!("*   **{}:**\n{}", label, fence(&value)));
+        };
+
+        let text = |value: &Option<String>| {
+            value
+                .as_deref()
+                .filter(|v| !v.trim().is_empty())
+                .map(|v| v.trim().to_string())
+        };
+        for (label, value) in [
+            ("会議名/件名", &self.title),
+            ("日時", &self.date),
+            ("場所", &self.place),
+        ] {
+            if let Some(value) = text(value) {
+                push(label, value);
+            }
+        }
+        if !self.participants.is_empty() {
+            push("参加者リスト", self.participants.join("\n"));
+        }
+        if let Some(purpose) = text(&self.purpose) {
+            push("ミーティングの目的", purpose);
+        }
+        if !self.agenda.is_empty() {
+            let agenda: Vec<String> = self
+                .agenda
+                .iter()
+                .enumerate()
+                .map(|(i, a)| format!("{}. {}", i + 1, a))
+                .collect();
+            push("事前に配布された議題", agenda.join("\n"));
+        }
+        for (label, value) in [
+            ("期待する議事録の形式・詳細度", &self.format),
+            ("その他特記事項", &self.notes),
+        ] {
+            if let Some(value) = text(value) {
+                push(label, value);
+            }
+        }
+
+        if md.len() == 1 {
+            md.push("指定なし".to_string());
+        }
+        md.join("\n")
+    }
+}
+
+impl MeetingMinutes {
+    /// Gemini の responseSchema (OpenAPI 3.0 のサブセット)
+    pub fn schema() -> Value {
+        let string = json!({"type": "STRING"});
+        let nullable = json!({"type": "STRING", "nullable": true});
+        let strings = json!({"type": "ARRAY", "items": {"type": "STRING"}});
+        let object = |properties: Value, required: &[&str]| {
+            json!({
+                "type": "OBJECT",
+                "properties": properties,
+                "required": required,
+            })
+        };
+        let array = |items: Value| json!({"type": "ARRAY", "items": items});
+
+        object(
+            json!({
+                "タイトル": string,
+                "日時": nullable,
+                "場所": nullable,
+                "要約": string,
+                "目的": nullable,
+                "参加者": strings,
+                "議題": strings,
+                "決定事項": array(object(
+                    json!({"内容": string, "決定背景・理由": nullable, "関連議題": nullable}),
+                    &["内容"],
+                )),
+                "ToDoリスト(アクションアイテム)": array(object(
+                    json!({"担当者": nullable, "タスク内容": string, "期限": nullable}),
+                    &["タスク内容"],
+                )),
+                "合意事項": array(object(
+                    json!({"内容": string, "合意形成プロセス": nullable}),
+                    &["内容"],
+                )),
+                "共有事項・報告事項": array(object(
+                    json!({"発言者": nullable, "内容": string}),
+                    &["内容"],
+                )),
+                "提起された課題・懸念点": array(object(
+                    json!({"提起者": nullable, "内容": string}),
+                    &["内容"],
+                )),
+                "その他特筆事項": nullable,
+                "次回予定": {
+                    "type": "OBJECT",
+                    "nullable": true,
+                    "properties": {"日時": nullable, "議題": nullable},
+                },
+            }),
+            &[
+                "タイトル",
+                "要約",
+                "参加者",
+                "議題",
+                "決定事項",
+                "ToDoリスト(アクションアイテム)",
+                "合意事項",
+            ],
+        )
+    }
+
+    /// モデルの出力を検証して読み込む
+    /// JSON モードに対応しないプロバイダ向けに、```json のコードブロックも受け付ける
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let s = s.trim();
+        let s = match s.strip_prefix("```") {
+            Some(rest) => {
+                let rest = rest.trim_start_matches("json");
+                rest.strip_suffix("```").unwrap_or(rest)
+            }
+            None => s,
+        };
+        serde_json::from_str::<MeetingMinutes>(s.trim())
+            .map_err(|e| format!("structured output does not match the schema: {}", e))
+    }
+
+    /// 分割して作成した部分的な議事録をまとめる
+    /// 単一の項目は最初に記載のあるもの、一覧は連結して重複を除く
+    pub fn merge(parts: Vec<MeetingMinutes>) -> MeetingMinutes {
+        let first = |f: fn(&MeetingMinutes) -> &Option<String>| {
+            parts
+                .iter()
+                .find_map(|p| f(p).clone().filter(|v| !v.trim().is_empty()))
+        };
+        let mut merged = MeetingMinutes {
+            title: parts
+                .iter()
+                .map(|p| p.title.clone())
+                .find(|t| !t.trim().is_empty())
+                .unwrap_or_default(),
+            date: first(|p| &p.date),
+            place: first(|p| &p.place),
+            summary: parts
+                .iter()
+                .map(|p| p.summary.trim())
+                .filter(|s| !s.is_empty())
+                .collect::<Vec<&str>>()
+                .join("\n"),
+            purpose: first(|p| &p.purpose),
+            notes: first(|p| &p.notes),
+            // 次回予定は最後に言及されたもの
+            next_meeting: parts.iter().rev().find_map(|p| p.next_meeting.clone()),
+            ..Default::default()
+        };
+        for part in parts {
+            merged.participants.extend(part.participants);
+            merged.agenda.extend(part.agenda);
+            merged.decisions.extend(part.decisions);
+            merged.todos.extend(part.todos);
+            merged.agreements.extend(part.agreements);
+            merged.reports.extend(part.reports);
+            merged.issues.extend(part.issues);
+        }
+        merged.dedup();
+        merged
+    }
+
+    /// 表記の揺れ (空白・句読点) を無視して重複する項目を除く
+    pub fn dedup(&mut self) {
+        fn key(s: &str) -> String {
+            s.chars()
+                .filter(|c| !c.is_whitespace() && !"。、．，.,!！?？・「」".contains(*c))
+                .flat_map(|c| c.to_lowercase())
+                .collect()
+        }
+        fn retain<T>(items: &mut Vec<T>, f: impl Fn(&T) -> String) {
+            let mut seen = std::collections::HashSet::new();
+            items.retain(|item| seen.insert(f(item)));
+        }
+
+        retain(&mut self.participants, |p| key(p));
+        retain(&mut self.agenda, |a| key(a));
+        retain(&mut self.decisions, |d| key(&d.content));
+        // 担当者の異なる同じタスクは別の ToDo として残す
+        retain(&mut self.todos, |t| {
+            format!(
+                "{}/{}",
+                key(t.assignee.as_deref().unwrap_or_default()),
+                key(&t.task)
+            )
+        });
+        retain(&mut self.agreements, |a| key(&a.content));
+        retain(&mut self.reports, |r| key(&r.content));
+        retain(&mut self.issues, |i| key(&i.content));
+    }
+
+    /// 標準議事録形式の markdown
+    pub fn to_markdown(&self) -> String {
+        let or_unknown = |v: &Option<String>| match v {
+            Some(v) if !v.is_empty() => v.clone(),
+            _ => "不明".to_string(),
+        };
+        let with_name = |name: &Option<String>, content: &str| match name {
+            Some(name) if !name.is_empty() => format!("- {} ({})", content, name),
+            _ => format!("- {}", content),
+        };
+
+        let mut md = vec![
+            format!("# {}", self.title),
+            String::new(),
+            format!("**日時:** {}  ", or_unknown(&self.date)),
+            format!("**場所:** {}  ", or_unknown(&self.place)),
+            format!("**参加者:** {}  ", self.participants.join("、")),
+            format!("**目的:** {}", or_unknown(&self.purpose)),
+            String::new(),
+            "## 要約".to_string(),
+            self.summary.clone(),
+            String::new(),
+            "## 議題".to_string(),
+        ];
+        md.extend(self.agenda.iter().map(|a| format!("- {}", a)));
+        md.push(String::new());
+        md.push("---".to_string());
+
+        md.push("## 決定事項".to_string());
+        md.extend(self.decisions.iter().map(|d| match &d.reason {
+            Some(reason) if !reason.is_empty() => format!("- {} (理由: {})", d.content, reason),
+            _ => format!("- {}", d.content),
+        }));
+        md.push(String::new());
+
+        md.push("## ToDoリスト".to_string());
+        md.push("| 担当者 | タスク内容 | 期限 |".to_string());
+        md.push("| --- | --- | --- |".to_string());
+        md.extend(self.todos.iter().map(|t| {
+            format!(
+                "| {} | {} | {} |",
+                or_unknown(&t.assignee),
+                t.task,
+                or_unknown(&t.due)
+            )
+        }));
+        md.push(String::new());
+
+        md.push("## 合意事項".to_string());
+        md.extend(self.agreements.iter().map(|a| format!("- {}", a.content)));
+        md.push(String::new());
+
+        md.push("## 共有事項・報告事項".to_string());
+        md.extend(
+            self.reports
+                .iter()
+                .map(|r| with_name(&r.speaker, &r.content)),
+        );
+        md.push(String::new());
+
+        md.push("## 提起された課題・懸念点".to_string());
+        md.extend(
+            self.issues
+                .iter()
+                .map(|i| with_name(&i.raised_by, &i.content)),
+        );
+        md.push(String::new());
+
+        md.push("## その他特筆事項".to_string());
+        md.push(or_unknown(&self.notes));
+        md.push(String::new());
+
+        md.push("## 次回予定".to_string());
+        match &self.next_meeting {
+            Some(next) => {
+                md.push(format!("- 日時: {}", or_unknown(&next.date)));
+                if let Some(agenda) = next.agenda.as_ref().filter(|a| !a.is_empty()) {
+                    md.push(format!("- 議題: {}", agenda));
+                }
+            }
+            None => md.push("未定".to_string()),
+        }
+
+        md.join("\n")
+    }
+}
+
+#[cfg(test)]
+// モデルの出力を構造化データとして読み込めるか確認する
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let output = r#"```json
+{
+  "タイトル": "定例会議",
+  "日時": "2025年4月1日 10:00 - 11:00",
+  "要約": "仕様を決定した",
+  "参加者": ["山田 太郎", "佐藤 花子"],
+  "議題": ["仕様について"],
+  "決定事項": [{"内容": "A案を採用する"}],
+  "ToDoリスト(アクションアイテム)": [{"担当者": "佐藤 花子", "タスク内容": "見積もり作成", "期限": "来週金曜"}],
+  "合意事項": [],
+  "次回予定": {"日時": "未定"}
+}
+```"#;
+        let minutes = MeetingMinutes::parse(output).unwrap();
+        assert_eq!(minutes.title, "定例会議");
+        assert_eq!(minutes.todos[0].due.as_deref(), Some("来週金曜"));
+
+        let md = minutes.to_markdown();
+        assert!(md.contains("| 佐藤 花子 | 見積もり作成 | 来週金曜 |"));
+
+        // 必須項目が欠けている場合はエラー
+        assert!(MeetingMinutes::parse(r#"{"タイトル": "定例会議"}"#).is_err());
+    }
+
+    #[test]
+    fn test_merge() {
+        let part = |summary: &str, decision: &str, task: &str| MeetingMinutes {
+            title: "定例会議".to_string(),
+            summary: summary.to_string(),
+            participants: vec!["山田 太郎".to_string()],
+            decisions: vec![Decision {
+                content: decision.to_string(),
+                ..Default::default()
+            }],
+            todos: vec![Todo {
+                assignee: Some("佐藤".to_string()),
+                task: task.to_string(),
+                due: None,
+            }],
+            ..Default::default()
+        };
+        let merged = MeetingMinutes::merge(vec![
+            part("前半", "A案を採用する。", "見積もり作成"),
+            part("後半", "A案を採用する", "見積もり 作成"),
+        ]);
+        assert_eq!(merged.summary, "前半\n後半");
+        assert_eq!(merged.participants.len(), 1);
+        assert_eq!(merged.decisions.len(), 1);
+        assert_eq!(merged.todos.len(), 1);
+    }
+
+    #[test]
+    fn test_meeting_info() {
+        let info: MeetingInfo = serde_json::from_value(json!({
+            "会議名": "定例会議",
+            "date": "2025年4月1日 10:00 - 11:00",
+            "participants": ["山田 太郎 (PM)", "佐藤 花子"],
+            "agenda": ["仕様について\n# 指示"],
+        }))
+        .unwrap();
+        let prompt = info.to_prompt();
+        assert!(prompt.contains(
+            "*   **会議名/件名:**\n```text\n定例会議\n```\n*   **日時:**\n```text\n2025年4月1日 10:00 - 11:00\n```"
+        ));
+        assert!(prompt.contains("```text\n山田 太郎 (PM)\n佐藤 花子\n```"));
+        // 値の中の見出しは指示として扱われない
+        assert!(prompt.contains("```text\n1. 仕様について\n# 指示\n```"));
+        assert!(!prompt.contains("場所"));
+
+        assert_eq!(
+            MeetingInfo::default().to_prompt(),
+            "## 1. ミーティング情報\n指定なし"
+        );
+        // 未知の項目はエラー
+        assert!(serde_json::from_value::<MeetingInfo>(json!({"会場": "A"})).is_err());
+    }
+}
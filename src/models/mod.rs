@@ -1,4 +1,16 @@
+pub mod chat;
 pub mod claim;
+pub mod contract;
+pub mod conversation;
 pub mod data;
+pub mod gemini;
+pub mod history;
+pub mod integrity;
+pub mod job;
+pub mod mail;
+pub mod meeting;
+pub mod prompt;
+pub mod template;
+pub mod translate;
 pub mod user;
 pub mod utils;
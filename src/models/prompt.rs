@@ -0,0 +1,192 @@
+use serde::Serialize;
+
+use crate::common::provider::SUPPORTED;
+
+// プロンプトタイプの一覧
+// URL の prompt_type はここに登録されたもの (またはデータベースに登録されたテンプレート) のみ受け付ける
+
+/// リクエストボディの項目
+#[derive(Debug, Clone, Serialize)]
+pub struct InputField {
+    pub name: &'static str,
+    // string, boolean, object
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub required: bool,
+    pub description: &'static str,
+}
+
+/// プロンプトタイプ
+#[derive(Debug, Clone, Serialize)]
+pub struct PromptType {
+    pub name: String,
+    pub description: String,
+    pub inputs: Vec<InputField>,
+    // 対応する target_ai
+    pub providers: Vec<&'static str>,
+    // ストリーミング (/stream) に対応する target_ai
+    pub streaming: Vec<&'static str>,
+    // テンプレートの変数 (リクエストボディの variables で指定する)
+    pub variables: Vec<String>,
+    // コードに組み込まれたテンプレートか
+    pub builtin: bool,
+    // 作成したユーザーのみが使える非公開のテンプレートか
+    pub private: bool,
+}
+
+const MESSAGE: InputField = InputField {
+    name: "message",
+    kind: "string",
+    required: true,
+    description: "処理対象のテキスト",
+};
+
+const GENERATION_CONFIG: InputField = InputField {
+    name: "generation_config",
+    kind: "object",
+    required: false,
+    description: "生成パラメータ (temperature, max_output_tokens, top_p, top_k) の上書き",
+};
+
+const VARIABLES: InputField = InputField {
+    name: "variables",
+    kind: "object",
+    required: true,
+    description: "テンプレートの変数の値 (変数名をキーとする文字列)",
+};
+
+impl PromptType {
+    fn new(name: &str, description: &str, inputs: Vec<InputField>, builtin: bool) -> Self {
+        PromptType {
+            name: name.to_string(),
+            description: description.to_string(),
+            inputs,
+            providers: SUPPORTED.to_vec(),
+            streaming: vec!["gemini"],
+            variables: Vec::new(),
+            builtin,
+            private: false,
+        }
+    }
+
+    /// データベースにのみ登録されたテンプレート
+    pub fn custom(name: &str, description: &str, variables: Vec<String>, private: bool) -> Self {
+        let mut inputs = vec![MESSAGE];
+        if !variables.is_empty() {
+            inputs.push(VARIABLES);
+        }
+        inputs.push(GENERATION_CONFIG);
+        PromptType {
+            variables,
+            private,
+            ..Self::new(name, description, inputs, false)
+        }
+    }
+}
+
+/// コードに組み込まれたプロンプトタイプ
+pub fn builtin() -> Vec<PromptType> {
+    vec![
+        PromptType::new(
+            "mail",
+            "クライアントに対するメール構文のリファクタリング",
+            vec![
+                InputField {
+                    description: "元のメール文章",
+                    ..MESSAGE
+                },
+                GENERATION_CONFIG,
+            ],
+            true,
+        ),
+        PromptType::new(
+            "meeting",
+            "議事録作成のための構造化ルールとミーティング情報を元に、文字起こしテキストを分析・構造化する",
+            vec![
+                InputField {
+                    description: "ミーティングの文字起こしテキスト",
+                    ..MESSAGE
+                },
+                InputField {
+                    name: "meeting",
+                    kind: "object",
+                    required: false,
+                    description: "既知のミーティング情報 (title, date, place, participants, purpose, agenda, format, notes)",
+                },
+                InputField {
+                    name: "structured",
+                    kind: "boolean",
+                    required: false,
+                    description: "議事録を構造化データ (JSON) で出力させる",
+                },
+                GENERATION_CONFIG,
+            ],
+            true,
+        ),
+        PromptType::new(
+            "integrity",
+            "文章中の整合性を確認する",
+            vec![
+                InputField {
+                    description: "監査対象のテキスト",
+                    ..MESSAGE
+                },
+                GENERATION_CONFIG,
+            ],
+            true,
+        ),
+        PromptType::new(
+            "translate",
+            "ビジネス文書の翻訳 (日本語⇄英語)。訳文・逆翻訳・用語集を出力する",
+            vec![
+                InputField {
+                    description: "原文",
+                    ..MESSAGE
+                },
+                InputField {
+                    name: "translate",
+                    kind: "object",
+                    required: false,
+                    description: "翻訳条件 (source, target: [ja, en]、formality: [polite (丁寧語), honorific (尊敬語・謙譲語), casual])。source を省略した場合は原文から判定する",
+                },
+                GENERATION_CONFIG,
+            ],
+            true,
+        ),
+        PromptType::new(
+            "contract",
+            "契約書・NDA の条項ごとのリスク評価、不足している標準条項、修正案を出力する",
+            vec![
+                InputField {
+                    description: "レビュー対象の契約書",
+                    ..MESSAGE
+                },
+                GENERATION_CONFIG,
+            ],
+            true,
+        ),
+        PromptType::new(
+            "chat",
+            "社内チャット (Slack, Teams) 向けに、相手に合わせたトーンと文字数でメッセージを書き換え、推奨順に複数の候補を出力する",
+            vec![
+                InputField {
+                    description: "元のメッセージ",
+                    ..MESSAGE
+                },
+                InputField {
+                    name: "chat",
+                    kind: "object",
+                    required: false,
+                    description: "調整条件 (channel: [slack, teams]、recipient: [peer, junior, senior, executive]、max_chars: 文字数の上限、alternatives: 候補の数)",
+                },
+                GENERATION_CONFIG,
+            ],
+            true,
+        ),
+    ]
+}
+
+/// 組み込みのプロンプトタイプを名前で取得する
+pub fn find(name: &str) -> Option<PromptType> {
+    builtin().into_iter().find(|p| p.name == name)
+}
@@ -0,0 +1,373 @@
+use std::{collections::HashMap, sync::LazyLock};
+
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+// プロンプトテンプレート
+// 各バージョンは作成後に変更しない。テンプレートごとに有効なバージョンを指す
+
+/// ユーザーの入力 (コードブロックで囲んだもの) を差し込む位置
+pub const INPUT_PLACEHOLDER: &str = "{{input}}";
+
+/// `{{name}}` 形式の変数 (名前は英数字・日本語・`_`)
+static VARIABLE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{\{\s*([\p{L}\p{N}_]+)\s*\}\}").unwrap());
+
+/// コードに組み込まれたテンプレートのバージョン番号
+/// データベースにバージョンがない場合に使用する
+pub const BUILTIN_VERSION: u32 = 0;
+
+/// テンプレートの有効なバージョンを指す
+/// ドキュメントIDはテンプレート名
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PromptTemplate {
+    pub name: String,
+    pub active_version: u32,
+    pub latest_version: u32,
+    pub updated_by: String,
+    pub updated_at: String,
+}
+
+/// テンプレートのバージョン
+/// ドキュメントIDは `{name}@{version}`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TemplateVersion {
+    pub name: String,
+    pub version: u32,
+    #[serde(default)]
+    pub description: String,
+    // 役割・指示
+    pub system: String,
+    // ユーザーメッセージ。{{input}} に入力を差し込む
+    pub user: String,
+    #[serde(default)]
+    pub note: Option<String>,
+    pub created_by: String,
+    pub created_at: String,
+}
+
+impl PromptTemplate {
+    /// まだバージョンがないテンプレート。組み込みのテンプレートを有効とする
+    pub fn new(name: &str) -> Self {
+        PromptTemplate {
+            name: name.to_string(),
+            active_version: BUILTIN_VERSION,
+            ..Default::default()
+        }
+    }
+
+    /// 次に作成するバージョンの番号
+    /// 前回の作成でポインタの保存に失敗し `latest_version` が作成済みのバージョンより遅れていても、
+    /// 既存のバージョンと重ならない番号にする
+    pub fn next_version(current: Option<&PromptTemplate>, versions: &[TemplateVersion]) -> u32 {
+        let latest = current.map(|t| t.latest_version).unwrap_or(0);
+        versions.iter().map(|v| v.version).fold(latest, u32::max) + 1
+    }
+
+    /// 作成したバージョンをポインタに反映する
+    /// `latest_version` は戻さず、有効なバージョンは `activate` の場合のみ切り替える
+    pub fn advance(self, number: u32, activate: bool, user_id: &str, now: &str) -> Self {
+        PromptTemplate {
+            active_version: if activate {
+                number
+            } else {
+                self.active_version
+            },
+            latest_version: self.latest_version.max(number),
+            updated_by: user_id.to_string(),
+            updated_at: now.to_string(),
+            ..self
+        }
+    }
+}
+
+impl TemplateVersion {
+    pub fn id(name: &str, version: u32) -> String {
+        format!("{}@{}", name, version)
+    }
+
+    /// テンプレート中の変数名 (出現順、{{input}} を除く)
+    pub fn variables(&self) -> Vec<String> {
+        variable_names(&format!("{}\n{}", self.system, self.user))
+            .into_iter()
+            .filter(|name| name != "input")
+            .collect()
+    }
+
+    /// リクエストボディの `variables` を検証する
+    /// 不足・余分なキーや文字列以外の値は、キー名を含むエラーとしてまとめて返す
+    pub fn check_variables(
+        &self,
+        values: Option<&Map<String, Value>>,
+    ) -> Result<HashMap<String, String>, Vec<String>> {
+        let expected = self.variables();
+        let empty = Map::new();
+        let values = values.unwrap_or(&empty);
+
+        let mut errors = Vec::new();
+        for name in &expected {
+            if !values.contains_key(name) {
+                errors.push(format!("missing variable: {}", name));
+            }
+        }
+        let mut resolved = HashMap::new();
+        for (key, value) in values {
+            if !expected.contains(key) {
+                errors.push(format!("unknown variable: {}", key));
+                continue;
+            }
+            match value.as_str() {
+                Some(v) => {
+                    resolved.insert(key.clone(), v.to_string());
+                }
+                None => errors.push(format!("variable must be a string: {}", key)),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(resolved)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// AIのレスポンスに記録する、どのテンプレートのどのバージョンを使ったか
+    pub fn reference(&self) -> TemplateRef {
+        TemplateRef {
+            name: self.name.clone(),
+            version: self.version,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TemplateRef {
+    pub name: String,
+    pub version: u32,
+}
+
+/// 管理者が新しいバージョンを作成する際のペイロード
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TemplateInput {
+    #[serde(default)]
+    pub description: String,
+    pub system: String,
+    pub user: String,
+    #[serde(default)]
+    pub note: Option<String>,
+    // 作成したバージョンを有効にするか
+    #[serde(default = "default_activate")]
+    pub activate: bool,
+}
+
+fn default_activate() -> bool {
+    true
+}
+
+impl TemplateInput {
+    pub fn validate(&self) -> Result<(), String> {
+        validate_parts(&self.system, &self.user)
+    }
+}
+
+/// 役割・指示は必須、ユーザーメッセージには入力の差し込み位置を1つだけ含める
+fn validate_parts(system: &str, user: &str) -> Result<(), String> {
+    if system.trim().is_empty() {
+        return Err("system is empty".to_string());
+    }
+    if user.matches(INPUT_PLACEHOLDER).count() != 1 {
+        return Err(format!(
+            "user must contain {} exactly once",
+            INPUT_PLACEHOLDER
+        ));
+    }
+    Ok(())
+}
+
+/// テキスト中の `{{name}}` 形式の変数名 (出現順、重複を含む)
+pub fn variable_occurrences(text: &str) -> Vec<String> {
+    VARIABLE
+        .captures_iter(text)
+        .map(|c| c[1].to_string())
+        .collect()
+}
+
+/// テキスト中の `{{name}}` 形式の変数名 (出現順、重複なし)
+pub fn variable_names(text: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for name in variable_occurrences(text) {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+/// 変数とユーザーの入力を差し込む
+/// 一度に置換するため、値に含まれる `{{...}}` は置換しない
+pub fn render(text: &str, input: &str, variables: &HashMap<String, String>) -> String {
+    VARIABLE
+        .replace_all(text, |c: &Captures| {
+            let name = &c[1];
+            if name == "input" {
+                return input.to_string();
+            }
+            match variables.get(name) {
+                Some(value) => value.clone(),
+                None => c[0].to_string(),
+            }
+        })
+        .into_owned()
+}
+
+/// 値のある変数のみを差し込み、値のない変数はそのまま残す
+/// `render` と同じく、値に含まれる `{{...}}` は置換しない
+pub fn fill(text: &str, values: &HashMap<String, String>) -> String {
+    VARIABLE
+        .replace_all(text, |c: &Captures| match values.get(&c[1]) {
+            Some(value) => value.clone(),
+            None => c[0].to_string(),
+        })
+        .into_owned()
+}
+
+/// ユーザーが作成する非公開のテンプレート
+/// ドキュメントIDは `{user_id}:{name}`。作成者のみ参照・使用できる
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UserTemplate {
+    pub user_id: String,
+    pub name: String,
+    // 更新のたびに増やす
+    pub version: u32,
+    #[serde(default)]
+    pub description: String,
+    pub system: String,
+    pub user: String,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl UserTemplate {
+    pub fn id(user_id: &str, name: &str) -> String {
+        format!("{}:{}", user_id, name)
+    }
+
+    pub fn to_version(&self) -> TemplateVersion {
+        TemplateVersion {
+            name: self.name.clone(),
+            version: self.version,
+            description: self.description.clone(),
+            system: self.system.clone(),
+            user: self.user.clone(),
+            note: None,
+            created_by: self.user_id.clone(),
+            created_at: self.updated_at.clone(),
+        }
+    }
+}
+
+/// ユーザーがテンプレートを作成・更新する際のペイロード
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UserTemplateInput {
+    #[serde(default)]
+    pub description: String,
+    pub system: String,
+    pub user: String,
+}
+
+impl UserTemplateInput {
+    pub fn validate(&self) -> Result<(), String> {
+        validate_parts(&self.system, &self.user)
+    }
+}
+
+/// テンプレート名はドキュメントIDとURLに使うため、英小文字・数字・`_`・`-` のみ
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-')
+}
+
+#[cfg(test)]
+// 管理者が登録するテンプレートの検証を確認する
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let input = |user: &str| TemplateInput {
+            description: String::new(),
+            system: "# 指示".to_string(),
+            user: user.to_string(),
+            note: None,
+            activate: true,
+        };
+        assert!(input("## 本文\n{{input}}").validate().is_ok());
+        // 入力の差し込み位置がない・複数ある
+        assert!(input("## 本文").validate().is_err());
+        assert!(input("{{input}}\n{{input}}").validate().is_err());
+
+        assert!(is_valid_name("mail_v2"));
+        assert!(!is_valid_name("mail@1"));
+        assert!(!is_valid_name(""));
+    }
+
+    #[test]
+    fn test_next_version() {
+        let version = |version: u32| TemplateVersion {
+            version,
+            ..Default::default()
+        };
+        assert_eq!(PromptTemplate::next_version(None, &[]), 1);
+
+        // ポインタの保存に失敗し、バージョン3が作成済みなのに latest_version が2のまま
+        let behind = PromptTemplate {
+            latest_version: 2,
+            active_version: 1,
+            ..PromptTemplate::new("mail")
+        };
+        let versions = [version(1), version(2), version(3)];
+        let number = PromptTemplate::next_version(Some(&behind), &versions);
+        assert_eq!(number, 4);
+
+        let template = behind.advance(number, false, "admin", "now");
+        assert_eq!((template.latest_version, template.active_version), (4, 1));
+        // 同時に作成された小さい番号で latest_version を戻さない
+        let template = template.advance(3, true, "admin", "now");
+        assert_eq!((template.latest_version, template.active_version), (4, 3));
+
+        // 初回に有効にしない場合は組み込みのテンプレートのまま
+        let template = PromptTemplate::new("mail").advance(1, false, "admin", "now");
+        assert_eq!(template.active_version, BUILTIN_VERSION);
+    }
+
+    #[test]
+    fn test_variables() {
+        let template = TemplateVersion {
+            system: "あなたは{{部署名}}の担当者です。".to_string(),
+            user: "## {{ 件名 }}\n{{input}}\n{{部署名}}".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(template.variables(), vec!["部署名", "件名"]);
+
+        let values = serde_json::json!({"部署名": "営業部", "件名": "{{input}}"});
+        let values = template.check_variables(values.as_object()).unwrap();
+        assert_eq!(
+            render(&template.user, "本文", &values),
+            "## {{input}}\n本文\n営業部"
+        );
+
+        // 不足・余分なキーはキー名を含めて返す
+        let values = serde_json::json!({"部署名": "営業部", "宛先": "山田"});
+        let errors = template.check_variables(values.as_object()).unwrap_err();
+        assert_eq!(
+            errors,
+            vec!["missing variable: 件名", "unknown variable: 宛先"]
+        );
+    }
+}
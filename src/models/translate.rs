@@ -0,0 +1,267 @@
+use serde::{Deserialize, Serialize};
+use unicode_script::Script;
+
+use crate::common::render;
+
+// ビジネス文書の翻訳 (日本語⇄英語)
+// 翻訳条件はリクエストボディの `translate` で指定する
+
+/// 項目名と見出しに含まれる語 (出力の順)
+const SECTIONS: &[(&str, &[&str])] = &[
+    ("translation", &["翻訳文", "Translation"]),
+    ("back_translation", &["逆翻訳", "Back Translation"]),
+    ("glossary", &["用語集", "Glossary"]),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Language {
+    #[serde(rename = "ja", alias = "日本語", alias = "japanese")]
+    Japanese,
+    #[serde(rename = "en", alias = "英語", alias = "english")]
+    English,
+}
+
+impl Language {
+    /// 日本語の文字 (漢字・ひらがな・カタカナ) とラテン文字の数から判定する
+    /// 日本語は1文字あたりの情報量が多いため、ラテン文字の 1/3 以上あれば日本語とする
+    pub fn detect(text: &str) -> Self {
+        let (mut japanese, mut latin) = (0, 0);
+        for c in text.chars() {
+            match Script::from(c) {
+                Script::Han | Script::Hiragana | Script::Katakana => japanese += 1,
+                Script::Latin => latin += 1,
+                _ => (),
+            }
+        }
+        if latin > 0 && japanese * 3 < latin {
+            Language::English
+        } else {
+            Language::Japanese
+        }
+    }
+
+    pub fn other(&self) -> Self {
+        match self {
+            Language::Japanese => Language::English,
+            Language::English => Language::Japanese,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Language::Japanese => "日本語",
+            Language::English => "英語",
+        }
+    }
+}
+
+/// 訳文の敬語・文体
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Formality {
+    #[default]
+    #[serde(rename = "polite", alias = "丁寧語")]
+    Polite,
+    #[serde(rename = "honorific", alias = "尊敬語・謙譲語")]
+    Honorific,
+    #[serde(rename = "casual", alias = "カジュアル")]
+    Casual,
+}
+
+impl Formality {
+    /// 訳文の言語ごとの文体の指示
+    fn instruction(&self, target: Language) -> &'static str {
+        match (self, target) {
+            (Formality::Polite, Language::Japanese) => {
+                "丁寧語（です・ます調）。社外にも失礼のない標準的なビジネス文体"
+            }
+            (Formality::Honorific, Language::Japanese) => {
+                "尊敬語・謙譲語を適切に使い分けた、クライアントや目上の方向けの改まった文体"
+            }
+            (Formality::Casual, Language::Japanese) => {
+                "社内の親しい相手向けのくだけた文体（だ・である調や簡潔な話し言葉）"
+            }
+            (Formality::Polite, Language::English) => {
+                "Standard business English. Polite and clear, without being stiff"
+            }
+            (Formality::Honorific, Language::English) => {
+                "Highly formal business English for clients and senior executives (e.g. \"We would be grateful if...\")"
+            }
+            (Formality::Casual, Language::English) => {
+                "Friendly, casual English suitable for colleagues (contractions are fine)"
+            }
+        }
+    }
+}
+
+/// リクエストボディの `translate`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TranslateOptions {
+    // 省略した場合は入力から判定する
+    #[serde(default)]
+    pub source: Option<Language>,
+    // 省略した場合は原文でない方の言語
+    #[serde(default)]
+    pub target: Option<Language>,
+    #[serde(default)]
+    pub formality: Formality,
+}
+
+impl TranslateOptions {
+    /// 原文と訳文の言語を決める
+    pub fn languages(&self, message: &str) -> Result<(Language, Language), String> {
+        let source = self.source.unwrap_or_else(|| Language::detect(message));
+        let target = self.target.unwrap_or(source.other());
+        if source == target {
+            return Err(format!(
+                "source and target must be different: {}",
+                source.label()
+            ));
+        }
+        Ok((source, target))
+    }
+
+    /// プロンプトの「翻訳条件」
+    pub fn to_prompt(&self, source: Language, target: Language) -> String {
+        [
+            "## 翻訳条件".to_string(),
+            format!("*   **原文の言語:** {}", source.label()),
+            format!("*   **訳文の言語:** {}", target.label()),
+            format!("*   **文体:** {}", self.formality.instruction(target)),
+        ]
+        .join("\n")
+    }
+}
+
+/// 用語集の項目
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GlossaryEntry {
+    pub source: String,
+    pub target: String,
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+impl GlossaryEntry {
+    /// `原文の用語 → 訳語: 補足` 形式の1行
+    fn parse(item: &str) -> Self {
+        let (terms, note) = match item.split_once(['：', ':']) {
+            Some((terms, note)) if terms.contains('→') => (terms, Some(note.trim())),
+            _ => (item, None),
+        };
+        let (source, target) = terms.split_once('→').unwrap_or((terms, ""));
+        GlossaryEntry {
+            source: source.trim().to_string(),
+            target: target.trim().to_string(),
+            note: note.filter(|n| !n.is_empty()).map(str::to_string),
+        }
+    }
+}
+
+/// 翻訳の出力
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Translation {
+    pub translation: String,
+    // 訳文を原文の言語に戻したもの (意味のずれの確認用)
+    pub back_translation: String,
+    pub glossary: Vec<GlossaryEntry>,
+    // 出力が指定の形式に沿っておらず、項目が揃っていない
+    pub fallback: bool,
+}
+
+impl Translation {
+    /// モデルの出力 (markdown) を項目に分ける
+    /// 翻訳文の見出しがない場合は、最初のコードブロック (なければ出力全体) を翻訳文とする
+    pub fn parse(output: &str) -> Self {
+        let mut sections = render::split_sections(output, SECTIONS);
+        let mut text = |key: &str| {
+            sections
+                .remove(key)
+                .map(|text| render::code_block(&text).unwrap_or(text))
+                .map(|text| text.trim().to_string())
+        };
+        let translation = text("translation");
+        let back_translation = text("back_translation");
+        let glossary: Vec<GlossaryEntry> = sections
+            .remove("glossary")
+            .map(|text| render::list_items(&text))
+            .unwrap_or_default()
+            .iter()
+            .map(|item| GlossaryEntry::parse(item))
+            .collect();
+
+        Translation {
+            fallback: translation.is_none() || back_translation.is_none(),
+            translation: translation.unwrap_or_else(|| {
+                render::code_block(output)
+                    .unwrap_or(output.to_string())
+                    .trim()
+                    .to_string()
+            }),
+            back_translation: back_translation.unwrap_or_default(),
+            glossary,
+        }
+    }
+}
+
+#[cfg(test)]
+// 言語の判定と出力の取り出しを確認する
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_languages() {
+        assert_eq!(
+            Language::detect("明日の会議はMeeting Room Aで行います。"),
+            Language::Japanese
+        );
+        assert_eq!(
+            Language::detect("Please review the 見積書 by Friday."),
+            Language::English
+        );
+
+        let options: TranslateOptions =
+            serde_json::from_value(serde_json::json!({"formality": "尊敬語・謙譲語"})).unwrap();
+        assert_eq!(options.formality, Formality::Honorific);
+        assert_eq!(
+            options.languages("Thank you for your email."),
+            Ok((Language::English, Language::Japanese))
+        );
+        let options = TranslateOptions {
+            target: Some(Language::Japanese),
+            ..Default::default()
+        };
+        assert!(options.languages("ご確認ください").is_err());
+    }
+
+    #[test]
+    fn test_parse() {
+        let output = r#"1. **翻訳文:**
+    ```
+    Thank you for your continued support.
+    ```
+2. **逆翻訳:**
+    ```
+    いつもご支援いただきありがとうございます。
+    ```
+3. **用語集:**
+    *   お世話になっております → Thank you for your continued support: 定型の挨拶
+    *   見積書 → quotation
+"#;
+        let translation = Translation::parse(output);
+        assert_eq!(
+            translation.translation,
+            "Thank you for your continued support."
+        );
+        assert!(translation.back_translation.starts_with("いつも"));
+        assert_eq!(
+            translation.glossary[0],
+            GlossaryEntry {
+                source: "お世話になっております".to_string(),
+                target: "Thank you for your continued support".to_string(),
+                note: Some("定型の挨拶".to_string()),
+            }
+        );
+        assert_eq!(translation.glossary[1].note, None);
+        assert!(!translation.fallback);
+    }
+}